@@ -0,0 +1,130 @@
+//! Annular Zernike radial polynomials (Mahajan, 1981).
+//!
+//! The radial polynomials are built numerically by Gram-Schmidt
+//! orthonormalisation of `r^m, r^(m+2), ...` over `obscuration <= r <= 1`,
+//! with polynomials represented by their coefficients in increasing powers of
+//! `r`. Combined with the usual `sqrt(2) cos(m theta)` (or sine) azimuthal
+//! term for `m > 0`, the resulting modes have unit RMS over the annulus and
+//! are mutually orthogonal on it. For zero obscuration they reduce to the
+//! Noll normalised circular Zernikes, which are `sqrt(n + 1)` times the
+//! circular modes of zernike disturbances (see `circular_zernike`). So an
+//! annular coefficient is a circular one divided by `sqrt(n + 1)` as the
+//! obscuration tends to zero.
+
+/// `<r^p>` averaged over the annulus, i.e., `2/(1-e^2) * integral(r^p r dr)`
+fn moment(p: usize, obscuration: f64) -> f64 {
+    let p = p as f64 + 2.0;
+    2.0 * (1.0 - obscuration.powf(p)) / (p * (1.0 - obscuration.powi(2)))
+}
+
+fn inner(a: &[f64], b: &[f64], obscuration: f64) -> f64 {
+    a.iter()
+        .enumerate()
+        .flat_map(|(i, ai)| {
+            b.iter()
+                .enumerate()
+                .map(move |(j, bj)| ai * bj * moment(i + j, obscuration))
+        })
+        .sum()
+}
+
+/// Coefficients (in increasing powers of `r`) of the annular radial
+/// polynomial of order `(n, m)`, where `n - m` is even.
+pub(crate) fn radial_polynomial(n: u32, m: u32, obscuration: f64) -> Vec<f64> {
+    let mut basis: Vec<Vec<f64>> = vec![];
+    for k in (m..=n).step_by(2) {
+        let mut p = vec![0.0; k as usize + 1];
+        p[k as usize] = 1.0;
+        for q in &basis {
+            let projection = inner(&p, q, obscuration);
+            p.iter_mut()
+                .zip(q)
+                .for_each(|(pi, qi)| *pi -= projection * qi);
+        }
+        let norm = inner(&p, &p, obscuration).sqrt();
+        p.iter_mut().for_each(|pi| *pi /= norm);
+        basis.push(p);
+    }
+    basis.pop().unwrap_or_default()
+}
+
+/// Evaluate a polynomial (coefficients in increasing powers) at `r`.
+pub(crate) fn evaluate(polynomial: &[f64], r: f64) -> f64 {
+    polynomial.iter().rev().fold(0.0, |acc, c| acc * r + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `<a b>` averaged over the annulus, by the midpoint rule
+    fn average(a: &[f64], b: &[f64], obscuration: f64) -> f64 {
+        let steps = 20_000;
+        let dr = (1.0 - obscuration) / steps as f64;
+        let integral: f64 = (0..steps)
+            .map(|i| obscuration + (i as f64 + 0.5) * dr)
+            .map(|r| evaluate(a, r) * evaluate(b, r) * r * dr)
+            .sum();
+        2.0 * integral / (1.0 - obscuration.powi(2))
+    }
+
+    #[test]
+    fn orthonormal_on_annulus() {
+        let obscuration = 0.3;
+        for m in 0..4 {
+            let radials: Vec<Vec<f64>> = (m..=8)
+                .step_by(2)
+                .map(|n| radial_polynomial(n, m, obscuration))
+                .collect();
+            for (i, a) in radials.iter().enumerate() {
+                for (j, b) in radials.iter().enumerate() {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    let value = average(a, b, obscuration);
+                    assert!((value - expected).abs() < 1e-6, "m={m} {i} {j}: {value}");
+                }
+            }
+        }
+    }
+
+    /// the circular radial polynomials, without Noll's `sqrt(n + 1)`
+    #[test]
+    fn circular_normalisation_unchanged() {
+        // (j, n, m, r, value) from R(n, m) and the sqrt(2) cos (or sine) term
+        let sqrt2 = std::f64::consts::SQRT_2;
+        for (j, n, m, r, value) in [
+            (1, 0, 0, 0.5, 1.0),
+            (2, 1, 1, 0.5, 0.5 * sqrt2),
+            (4, 2, 0, 0.5, -0.5),
+            (6, 2, 2, 0.5, 0.25 * sqrt2),
+            (8, 3, 1, 0.5, -0.625 * sqrt2),
+            (11, 4, 0, 0.5, -0.125),
+            (11, 4, 0, 1.0, 1.0),
+        ] {
+            let circular = crate::circular_zernike(j, n, m, r, 0.0);
+            assert!((circular - value).abs() < 1e-9, "j={j}: {circular}");
+        }
+    }
+
+    #[test]
+    fn circular_without_obscuration() {
+        for j in 1..=28 {
+            let (n, m) = crate::zernike_index::Convention::Noll
+                .index_to_nm(j)
+                .unwrap();
+            let m = m.unsigned_abs();
+            let radial = radial_polynomial(n, m, 1e-9);
+            let theta = 0.3;
+            // even j are cosine modes, odd j are sine modes
+            let azimuthal = match (m, j % 2) {
+                (0, _) => 1.0,
+                (_, 0) => std::f64::consts::SQRT_2 * (m as f64 * theta).cos(),
+                _ => std::f64::consts::SQRT_2 * (m as f64 * theta).sin(),
+            };
+            for r in [0.1, 0.5, 0.9, 1.0] {
+                let annular = evaluate(&radial, r) * azimuthal;
+                let circular = (n as f64 + 1.0).sqrt() * crate::circular_zernike(j, n, m, r, theta);
+                assert!((annular - circular).abs() < 1e-6, "j={j} r={r}");
+            }
+        }
+    }
+}
@@ -9,11 +9,24 @@
 //! e.g., `crate::Disturbance` for the normal non-config type, and simply
 //! (e.g.) `Disturbance` within this config module.
 
+use rao::Vec2D;
 use serde::{Deserialize, Serialize};
-use std::{fs, str::FromStr, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    str::FromStr,
+    sync::Arc,
+};
 use thiserror::Error;
 
-use crate::System;
+use crate::{
+    System,
+    dm::ActuatorGrid,
+    mapfile::read_map,
+    noise::NoiseModel,
+    screen::{PhaseMap, PhaseScreen},
+    zernike_index::{Convention, NmCoefficient},
+};
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -21,19 +34,200 @@ pub enum ConfigError {
     Serialization(#[from] serde_json::Error),
     #[error("config file io error: {0}")]
     OpenConfig(#[from] std::io::Error),
+    #[error("invalid turbulence profile {0}: {1}")]
+    Profile(String, String),
+    #[error("invalid disturbance {0}: {1}")]
+    InvalidDisturbance(String, String),
+    #[error("invalid map file {0}: {1}")]
+    MapFile(String, String),
+    #[error("invalid pupil: {0}")]
+    InvalidPupil(String),
+    #[error("invalid sensor {0}: {1}")]
+    InvalidSensor(String, String),
+    #[error("invalid optical path {0}: {1}")]
+    InvalidPath(String, String),
+    #[error("invalid output {0}: {1}")]
+    InvalidOutput(String, String),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
+    /// telescope pupil, if absent then sensors are unobstructed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pupil: Option<Pupil>,
+    /// unit of the phase of every disturbance, i.e., of zernike
+    /// coefficients, DM commands and phase maps, and so of phase metrics
+    /// such as the wavefront error of an imager. Von Karman screens are
+    /// converted into it from radians at the wavelength of their r0, and
+    /// phase maps from their own unit (if any). Metrics at a wavelength
+    /// require a unit. Without one, the phase unit is up to the user, and
+    /// von Karman screens are left in radians.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unit: Option<PhaseUnit>,
+    /// light sources, which sensors can refer to by id
+    #[serde(default)]
+    sources: Vec<Source>,
+    /// optical paths, which determine the disturbances seen by each sensor
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<Path>,
     disturbances: Vec<Disturbance>,
     sensors: Vec<Sensor>,
     outputs: Vec<Output>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Pupil {
+    /// outer diameter (in metres)
+    diameter: f64,
+    /// central obscuration, as a fraction of the outer diameter
+    #[serde(default)]
+    obscuration: f64,
+    /// number of spiders, evenly spaced in angle starting along +x
+    #[serde(default)]
+    spiders: usize,
+    /// spider width (in metres)
+    #[serde(default)]
+    spider_width: f64,
+    /// explicit spider angles (in degrees, anticlockwise from +x), instead
+    /// of evenly spaced spiders
+    #[serde(default)]
+    spider_angles: Vec<f64>,
+    /// optional transmission mask file, in any format supported for
+    /// `PhaseMap`s, covering the outer diameter. Transmissive where > 0.5.
+    #[serde(default)]
+    mask: Option<String>,
+    /// keep masked samples in measurement vectors as NaN (`null` in the
+    /// results json) instead of dropping them
+    #[serde(default)]
+    flag_masked: bool,
+}
+
+/// Unit of phase, either radians at a reference wavelength:
+/// ```json
+/// "unit": {"Radians": {"wavelength": 5e-7}}
+/// ```
+/// or an optical path difference, e.g., `"unit": "Nanometres"`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+enum PhaseUnit {
+    Radians {
+        /// reference wavelength (in metres)
+        wavelength: f64,
+    },
+    Metres,
+    Micrometres,
+    Nanometres,
+}
+
+impl PhaseUnit {
+    /// optical path difference (in metres) per unit of phase
+    fn metres(&self) -> f64 {
+        match self {
+            PhaseUnit::Radians { wavelength } => wavelength / (2.0 * std::f64::consts::PI),
+            PhaseUnit::Metres => 1.0,
+            PhaseUnit::Micrometres => 1e-6,
+            PhaseUnit::Nanometres => 1e-9,
+        }
+    }
+
+    /// radians at `wavelength` (in metres) per unit of phase
+    fn radians_at(&self, wavelength: f64) -> f64 {
+        2.0 * std::f64::consts::PI * self.metres() / wavelength
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Source {
     /// id must be unique per config file
     pub id: String,
+    /// direction (in arcsec)
+    direction: (f64, f64),
+    /// distance from the telescope (in metres), e.g., ~90e3 for a LGS, a
+    /// few metres for a calibration-unit source, or very large for a NGS
+    altitude: f64,
+    /// wavelength (in metres), the default imaging wavelength of imagers
+    /// looking at the source
+    wavelength: f64,
+    /// photons per square metre of pupil per frame, used for detector noise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flux: Option<f64>,
+    /// entry point (see [`Element::Entry`]) at which the light of the source
+    /// enters the optical paths, by default it comes from the sky and passes
+    /// through every surface of a path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+}
+
+/// A named optical path, i.e., the ordered surfaces that light passes
+/// through on the way to a sensor, continuing on from the end of another
+/// path (if any). For example:
+/// ```json
+/// "paths": [
+///     {"id": "common", "elements": ["turbulence", "dsm", {"entry": "calibration unit"}, "adc"]},
+///     {"id": "lgs", "from": "common", "elements": ["lgs dichroic"]}
+/// ]
+/// ```
+/// where a sensor on the `lgs` path sees all four surfaces when looking at a
+/// sky source, but only `adc` and `lgs dichroic` when looking at a source
+/// entering at the calibration unit.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Path {
+    /// id must be unique per config file
+    id: String,
+    /// path that this one branches off from the end of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    elements: Vec<Element>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Element {
+    /// disturbance id
+    Surface(String),
+    /// point at which sources can enter the path, e.g., a calibration unit
+    Entry { entry: String },
+}
+
+/// The source seen by a sensor, either one of the config `sources`:
+/// ```json
+/// "source": "lgs1"
+/// ```
+/// or given in place by its direction (in arcsec) and altitude (in metres):
+/// ```json
+/// "direction": [0.0, 10.0], "gsalt": 90e3
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Beam {
+    Source { source: String },
+    Direction { direction: (f64, f64), gsalt: f64 },
+}
+
+/// direction (in arcsec), altitude (in metres) and flux (if known) of a source
+type ResolvedSource = ((f64, f64), f64, Option<f64>);
+
+impl Beam {
+    /// wavelength (in metres) of the source, if the beam refers to one
+    fn wavelength(&self, sources: &[Source]) -> Option<f64> {
+        match self {
+            Beam::Source { source } => sources
+                .iter()
+                .find(|s| &s.id == source)
+                .map(|s| s.wavelength),
+            Beam::Direction { .. } => None,
+        }
+    }
+
+    fn resolve(&self, sources: &[Source]) -> Result<ResolvedSource, String> {
+        match self {
+            Beam::Source { source } => sources
+                .iter()
+                .find(|s| &s.id == source)
+                .map(|s| (s.direction, s.altitude, s.flux))
+                .ok_or(format!("unknown source {source}")),
+            Beam::Direction { direction, gsalt } => Ok((*direction, *gsalt, None)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -41,13 +235,222 @@ enum Disturbance {
     Zernike {
         /// id must be unique per config file
         id: String,
-        /// zernike coefficients
-        coeffs: Vec<f64>,
+        /// zernike coefficients (in the phase `unit`). Annular modes have
+        /// unit RMS over the annulus, while circular modes have an RMS of
+        /// `1/sqrt(n + 1)` over the disk, so a circular coefficient is
+        /// `sqrt(n + 1)` times the RMS of its mode (see the README)
+        coeffs: Coefficients,
+        /// indexing convention of dense coefficients and sparse `j` entries
+        #[serde(default)]
+        convention: Convention,
         /// basis radius (in metres)
         radius: f64,
         /// altitude
         altitude: f64,
+        /// central obscuration ratio, if non-zero then annular zernikes
+        /// (orthonormal over the annulus) are used instead of circular ones
+        #[serde(default)]
+        obscuration: f64,
+        /// behaviour when sampled outside of the (annular) unit disk
+        #[serde(default)]
+        outside: Outside,
+    },
+    VonKarman {
+        /// id must be unique per config file
+        id: String,
+        /// Fried parameter (in metres)
+        r0: f64,
+        /// wavelength that `r0` is defined at (in metres)
+        #[serde(default = "default_r0_wavelength")]
+        wavelength: f64,
+        /// outer scale (in metres)
+        l0: f64,
+        /// altitude
+        altitude: f64,
+        /// minimum width of the square phase screen (in metres)
+        size: f64,
+        /// distance between phase screen samples (in metres)
+        pitch: f64,
+        /// random seed, the same seed always produces the same screen
+        seed: u64,
+    },
+    /// Multi-layer turbulence, expanded into one von Karman phase screen per
+    /// layer of the profile. Outputs refer to the whole profile by its id.
+    Turbulence {
+        /// id must be unique per config file
+        id: String,
+        /// integrated Fried parameter (in metres)
+        r0: f64,
+        /// wavelength that `r0` is defined at (in metres)
+        #[serde(default = "default_r0_wavelength")]
+        wavelength: f64,
+        /// outer scale (in metres), common to all layers
+        l0: f64,
+        /// Cn2 profile
+        profile: Profile,
+        /// minimum width of each square phase screen (in metres)
+        size: f64,
+        /// distance between phase screen samples (in metres)
+        pitch: f64,
+        /// random seed, each layer uses a seed derived from this one
+        seed: u64,
+        /// time (in seconds) to translate each layer by its wind vector
+        #[serde(default)]
+        time: f64,
+    },
+    /// Deformable mirror with a square grid of actuators
+    Dm {
+        /// id must be unique per config file
+        id: String,
+        /// number of actuators across the grid
+        nact: usize,
+        /// actuator pitch (in metres, at the conjugation altitude)
+        pitch: f64,
+        /// conjugation altitude
+        altitude: f64,
+        /// rotation of the actuator grid (in degrees)
+        #[serde(default)]
+        rotation: f64,
+        /// offset of the grid centre (in metres)
+        #[serde(default)]
+        offset: (f64, f64),
+        /// actuator influence function
+        influence: Influence,
+        /// actuator commands (in the phase `unit`), row-major (x fastest),
+        /// `nact * nact` long. An empty vector is a flat mirror.
+        #[serde(default)]
+        commands: Vec<f64>,
     },
+    /// Zonal phase map on a regular grid, loaded from a `.npy` file or a raw
+    /// little-endian binary file with a `.json` sidecar (see `mapfile`).
+    PhaseMap {
+        /// id must be unique per config file
+        id: String,
+        /// path to the map file
+        file: String,
+        /// physical width and height covered by the map (in metres)
+        extent: (f64, f64),
+        /// conjugation altitude
+        altitude: f64,
+        /// unit of the values in the file, e.g., `"Nanometres"` for a
+        /// measured surface, converted into the phase `unit` (which is then
+        /// required). By default the values are in the phase `unit`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit: Option<PhaseUnit>,
+        /// multiplier applied to the map after converting it, e.g., -2.0 for
+        /// the wavefront reflected off a measured surface
+        #[serde(default = "unit_scale")]
+        scale: f64,
+        #[serde(default)]
+        interpolation: Interpolation,
+    },
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+fn unit_magnification() -> (f64, f64) {
+    (1.0, 1.0)
+}
+
+fn default_r0_wavelength() -> f64 {
+    500e-9
+}
+
+fn default_oversampling() -> usize {
+    2
+}
+
+fn default_min_illumination() -> f64 {
+    0.5
+}
+
+fn default_edge_points() -> u32 {
+    2
+}
+
+fn default_curvature_points() -> usize {
+    8
+}
+
+fn default_density() -> usize {
+    32
+}
+
+fn default_tolerance() -> f64 {
+    0.02
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+enum Interpolation {
+    #[default]
+    Bilinear,
+    Bicubic,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+enum Influence {
+    /// Gaussian influence function with a given coupling to adjacent actuators
+    /// (strictly between 0 and 1)
+    Gaussian { coupling: f64 },
+    /// bicubic spline influence function
+    Bicubic,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+enum Outside {
+    #[default]
+    Extrapolate,
+    Zero,
+    Error,
+    Clamp,
+}
+
+/// Zernike coefficients, either dense:
+/// ```json
+/// "coeffs": [0.0, 0.1, 0.2]
+/// ```
+/// starting from piston in the chosen convention, or sparse:
+/// ```json
+/// "coeffs": [{"n": 2, "m": 0, "value": 0.1}, {"j": 5, "value": 0.2}]
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Coefficients {
+    Dense(Vec<f64>),
+    Sparse(Vec<Coefficient>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Coefficient {
+    /// mode addressed by radial and (signed) azimuthal order, where positive
+    /// `m` is a cosine mode and negative `m` is a sine mode
+    Nm { n: u32, m: i32, value: f64 },
+    /// mode addressed by single index in the chosen convention
+    J { j: u32, value: f64 },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+enum Profile {
+    /// explicit list of layers
+    Layers(Vec<Layer>),
+    /// ESO-style profile text file, with one layer per line and columns:
+    /// altitude (m), fractional weight, wind speed (m/s), wind direction (deg).
+    /// Lines starting with `#` are ignored.
+    File(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Layer {
+    /// altitude (in metres)
+    altitude: f64,
+    /// fractional Cn2 weight, normalised over the profile
+    weight: f64,
+    /// wind vector (in metres per second)
+    #[serde(default)]
+    wind: (f64, f64),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -55,13 +458,58 @@ enum Sensor {
     Shwfs {
         /// id must be unique per config file
         id: String,
-        /// nsubs across aperture
+        /// nsubs across aperture, unused for explicit layouts
+        #[serde(default)]
         nsubx: usize,
+        /// subaperture width (in metres), also the lenslet pitch of square
+        /// and hexagonal layouts
         subwidth: f64,
+        /// arrangement of the lenslets, which is shifted by `centre` and then
+        /// rotated by `rotation`
+        #[serde(default)]
+        layout: Layout,
         centre: (f64, f64),
         rotation: f64,
-        direction: (f64, f64),
-        gsalt: f64,
+        /// mirror the sensor x axis wrt the pupil, e.g., after an odd number
+        /// of folds
+        #[serde(default)]
+        flip_x: bool,
+        /// mirror the sensor y axis wrt the pupil
+        #[serde(default)]
+        flip_y: bool,
+        /// magnification of the pupil image along the sensor x and y axes,
+        /// relative to the nominal magnification that sizes are given at
+        #[serde(default = "unit_magnification")]
+        magnification: (f64, f64),
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// minimum fraction of a subaperture that the pupil must illuminate
+        /// for the subaperture to be valid, only used when there is a pupil
+        #[serde(default = "default_min_illumination")]
+        min_illumination: f64,
+        /// slope estimator
+        #[serde(default)]
+        estimator: SlopeEstimator,
+        /// length of each sampled edge (in metres), `subwidth` by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        edge_length: Option<f64>,
+        /// separation of the sampled edges, or lines (in metres), `subwidth`
+        /// by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        edge_separation: Option<f64>,
+        /// number of points sampled along each edge
+        #[serde(default = "default_edge_points")]
+        npoints: u32,
+        /// altitude that the lines of each edge converge to (in metres),
+        /// parallel lines (i.e., infinitely far) by default
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        altitude: Option<f64>,
+        /// detector noise added to measurement vectors, noiseless if absent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        noise: Option<Noise>,
     },
     Imager {
         /// id must be unique per config file
@@ -71,27 +519,436 @@ enum Sensor {
         pitch: f64,
         centre: (f64, f64),
         rotation: f64,
-        direction: (f64, f64),
-        gsalt: f64,
+        /// mirror the sensor x axis wrt the pupil, e.g., after an odd number
+        /// of folds
+        #[serde(default)]
+        flip_x: bool,
+        /// mirror the sensor y axis wrt the pupil
+        #[serde(default)]
+        flip_y: bool,
+        /// magnification of the pupil image along the sensor x and y axes,
+        /// relative to the nominal magnification that sizes are given at
+        #[serde(default = "unit_magnification")]
+        magnification: (f64, f64),
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// Modulated pyramid WFS, reporting normalised x signals at each pupil
+    /// sample followed by the y signals.
+    Pyramid {
+        /// id must be unique per config file
+        id: String,
+        /// pupil samples across aperture
+        nsamples: usize,
+        /// distance between pupil samples (in metres)
+        pitch: f64,
+        centre: (f64, f64),
+        rotation: f64,
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// modulation radius (in arcsec), converted into slopes of the phase
+        /// `unit`, which is required. Must be positive, i.e., unmodulated
+        /// pyramids aren't supported.
+        modulation: f64,
+        /// optical gain applied to the signals
+        #[serde(default = "unit_scale")]
+        gain: f64,
+        #[serde(default)]
+        regime: PyramidRegime,
+    },
+    /// Least-squares zernike coefficients of the phase over a circular pupil,
+    /// in the same basis (and Noll order) as zernike disturbances.
+    ZernikeProjection {
+        /// id must be unique per config file
+        id: String,
+        /// highest radial order fitted, all modes up to and including this
+        /// order are fitted
+        order: u32,
+        /// basis radius (in metres)
+        radius: f64,
+        /// phase samples across the basis diameter
+        nsamples: usize,
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// Curvature WFS, reporting `(I1 - I2) / (I1 + I2)` for each valid
+    /// subaperture, in the geometric optics approximation. The signals are
+    /// dimensionless, converting the phase into wavefronts in metres through
+    /// the phase `unit`, which is required.
+    Curvature {
+        /// id must be unique per config file
+        id: String,
+        /// nsubs across aperture, unused for explicit layouts
+        #[serde(default)]
+        nsubx: usize,
+        /// subaperture width (in metres), also the pitch of square and
+        /// hexagonal layouts
+        subwidth: f64,
+        /// arrangement of the subapertures, which is shifted by `centre` and
+        /// then rotated by `rotation`
+        #[serde(default)]
+        layout: Layout,
+        centre: (f64, f64),
+        rotation: f64,
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// focal length of the beam at the defocused images (in metres)
+        focal_length: f64,
+        /// distance of each defocused image from focus (in metres)
+        extra_focal: f64,
+        /// number of points sampled along each side of a subaperture
+        #[serde(default = "default_curvature_points")]
+        npoints: usize,
+        /// minimum fraction of a subaperture that the pupil must illuminate
+        /// for the subaperture to be valid, only used when there is a pupil
+        #[serde(default = "default_min_illumination")]
+        min_illumination: f64,
+    },
+    /// Low-order WFS with few, large subapertures (e.g., 2x2, or 1x1 for a
+    /// tip-tilt sensor), reporting the average gradient over the illuminated
+    /// part of each subaperture, in the same units and order as a SH-WFS.
+    LowOrder {
+        /// id must be unique per config file
+        id: String,
+        /// nsubs across aperture
+        nsubx: usize,
+        subwidth: f64,
+        centre: (f64, f64),
+        rotation: f64,
+        #[serde(flatten)]
+        beam: Beam,
+        /// optical path ending at this sensor, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// sampling cells across each subaperture
+        #[serde(default = "default_density")]
+        density: usize,
+        /// minimum fraction of a subaperture that the pupil must illuminate
+        /// for the subaperture to be valid, only used when there is a pupil
+        #[serde(default = "default_min_illumination")]
+        min_illumination: f64,
+        /// largest difference in the illuminated fraction (or centroid, as a
+        /// fraction of `subwidth`) of any subaperture from a sampling 8 times
+        /// denser, beyond which the sampling is considered unconverged
+        #[serde(default = "default_tolerance")]
+        tolerance: f64,
+        /// detector noise added to measurement vectors, noiseless if absent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        noise: Option<Noise>,
     },
 }
 
+/// Lenslet layout of a SH-WFS, or subaperture layout of a curvature WFS.
+/// Subaperture illumination is always computed over a square of width
+/// `subwidth` about each lenslet centre.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+enum Layout {
+    /// `nsubx` by `nsubx` square grid
+    #[default]
+    Square,
+    /// hexagonal lattice, with rows along x, within a circle `nsubx`
+    /// lenslets across
+    Hexagonal,
+    /// explicit lenslet centres (in metres)
+    Positions(Vec<(f64, f64)>),
+    /// text file of lenslet centres (in metres), with one `x, y` pair per
+    /// line. Lines starting with `#` are ignored.
+    File(String),
+}
+
+impl Layout {
+    /// Lenslet centres (in metres), before shifting and rotating them.
+    fn centres(self, nsubx: usize, subwidth: f64) -> Result<Vec<Vec2D>, String> {
+        let centres: Vec<Vec2D> = match self {
+            Layout::Square => crate::square_grid(nsubx, subwidth, (0.0, 0.0), 0.0),
+            Layout::Hexagonal => crate::hexagonal_grid(nsubx, subwidth),
+            Layout::Positions(positions) => positions
+                .into_iter()
+                .map(|(x, y)| Vec2D::new(x, y))
+                .collect(),
+            Layout::File(filename) => read_positions(&filename)?,
+        };
+        if centres.is_empty() {
+            return Err("lenslet layout is empty".to_string());
+        }
+        Ok(centres)
+    }
+}
+
+/// Detector noise of a centroiding WFS, giving a slope noise on each
+/// measurement, which is converted from arcsec into slope units (phase
+/// `unit` per metre), so it requires a phase `unit`. Partially illuminated
+/// subapertures receive a proportionally lower flux.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Noise {
+    /// photons per frame reaching a fully illuminated subaperture, by
+    /// default the flux of the source over the subaperture area
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flux: Option<f64>,
+    /// read noise (electrons rms per pixel)
+    #[serde(default)]
+    read_noise: f64,
+    /// sky background (photons per pixel per frame)
+    #[serde(default)]
+    background: f64,
+    /// pixel scale (arcsec per pixel)
+    pixel_scale: f64,
+    /// spot FWHM (arcsec)
+    spot_size: f64,
+    /// random seed, the same seed always produces the same noise
+    seed: u64,
+}
+
+impl Noise {
+    /// `source_flux` is the flux of the source (if known) over a subaperture
+    fn into_system(
+        self,
+        source_flux: Option<f64>,
+        unit: Option<&PhaseUnit>,
+    ) -> Result<NoiseModel, String> {
+        let flux = self
+            .flux
+            .or(source_flux)
+            .ok_or("noise needs a flux, either its own or from the source")?;
+        let unit = unit.ok_or("noise needs a phase unit, to convert it into slopes")?;
+        Ok(NoiseModel::new(
+            flux,
+            self.read_noise,
+            self.background,
+            self.pixel_scale,
+            self.spot_size,
+            crate::AS2RAD / unit.metres(),
+            self.seed,
+        ))
+    }
+}
+
+/// Estimator of SH-WFS slopes, each of which is a difference of phase
+/// divided by a separation.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+enum SlopeEstimator {
+    /// average phase along one edge minus that along the opposite edge
+    #[default]
+    TwoEdge,
+    /// phase at one edge centre minus that at the opposite edge centre
+    TwoLine,
+    /// phase difference over a small `step` (in metres) about the centre of
+    /// the subaperture, approximating the gradient at the centre. This is an
+    /// alias of `TwoLine` with an `edge_separation` of `step`.
+    CentreDifference { step: f64 },
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+enum PyramidRegime {
+    /// sinusoidal response, saturating beyond the modulation radius
+    #[default]
+    Geometric,
+    /// linear response, with the small-slope gain of the geometric regime
+    Linear,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Output {
     /// id of this output (e.g., "science path")
     pub id: String,
-    /// disturbance ids
-    pub disturbances: Vec<String>,
+    /// disturbances, by id and optional gain. Sensors on an optical path
+    /// only see the listed disturbances which are visible along their path,
+    /// or all of the visible disturbances (with unit gain) if none are listed.
+    #[serde(default)]
+    pub disturbances: Vec<OutputDisturbance>,
     /// sensor ids
     pub sensors: Vec<String>,
     /// quality metric
     pub metric: Metric,
 }
 
+/// Disturbance included in an output, either by id alone (with unit gain):
+/// ```json
+/// "dsm"
+/// ```
+/// or with a signed gain that it is scaled by:
+/// ```json
+/// {"id": "dsm", "gain": -2.0}
+/// ```
+/// e.g., for a mirror correcting an aberration in reflection.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum OutputDisturbance {
+    Id(String),
+    Scaled { id: String, gain: f64 },
+}
+
+impl OutputDisturbance {
+    fn id(&self) -> &String {
+        match self {
+            OutputDisturbance::Id(id) | OutputDisturbance::Scaled { id, .. } => id,
+        }
+    }
+
+    fn gain(&self) -> f64 {
+        match self {
+            OutputDisturbance::Id(_) => 1.0,
+            OutputDisturbance::Scaled { gain, .. } => *gain,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 enum Metric {
     WafefrontError,
     MeasurementVector,
+    /// least-squares coefficients of the phase sampled by each imager
+    ZernikeProjection {
+        /// number of Noll modes fitted, starting from piston
+        nmodes: u32,
+        /// basis radius (in metres)
+        radius: f64,
+    },
+    /// same as `WafefrontError`, after removing zernike modes from what each
+    /// sensor measures, e.g., tip-tilt for a LGS WFS
+    ResidualWavefrontError {
+        remove: Removal,
+        /// basis radius (in metres)
+        radius: f64,
+    },
+    /// image quality of the PSF of each imager, from the Fourier transform
+    /// of its pupil field (requires a phase `unit`)
+    Image {
+        /// imaging wavelength (in metres), by default that of the source
+        /// which the imagers look at
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        wavelength: Option<f64>,
+        /// zero padding factor of the pupil field, 2 for a Nyquist sampled PSF
+        #[serde(default = "default_oversampling")]
+        oversampling: usize,
+        quantity: ImageQuantity,
+        /// if present, the PSF of each imager is written to
+        /// `{psf_prefix}{imager id}.npy`, normalised to a total of 1
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        psf_prefix: Option<String>,
+    },
+    /// `exp(-sigma^2)` for the piston-removed phase RMS `sigma` of each
+    /// imager, in radians at the imaging wavelength (requires a phase
+    /// `unit`). Much cheaper than `Image`, but only accurate for small
+    /// `sigma`.
+    MarechalStrehl {
+        /// imaging wavelength (in metres), by default that of the source
+        /// which the imagers look at
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        wavelength: Option<f64>,
+    },
+    /// same as `WafefrontError`, along with the wavefront error due to each
+    /// disturbance alone, reported as a labelled `budget`
+    ErrorBudget {
+        /// also report the cross term of each pair of disturbances, signed
+        /// so that the signed squares of all terms add up to the square of
+        /// the total. This only holds for sensors that are linear in the
+        /// phase, i.e., not for pyramids in the `Geometric` regime.
+        #[serde(default)]
+        cross_terms: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ImageQuantity {
+    /// PSF peak relative to the diffraction limited peak
+    Strehl,
+    /// full width at half maximum (in arcsec), from the area above half of
+    /// the peak, so an oversampling of 4 or more is recommended
+    Fwhm,
+    /// fraction of the energy within a radius (in arcsec) of the peak
+    EncircledEnergy(f64),
+}
+
+/// Zernike modes removed from a wavefront error. Each named removal includes
+/// the lower orders, e.g., `TipTilt` also removes piston.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Removal {
+    Piston,
+    TipTilt,
+    Focus,
+    /// Noll indices of the removed modes
+    Modes(Vec<u32>),
+}
+
+impl Metric {
+    fn into_system(
+        self,
+        sensors: &[Arc<crate::Sensor>],
+        unit: Option<&PhaseUnit>,
+        // source wavelength (if any) of every sensor, by id
+        wavelengths: &BTreeMap<String, Option<f64>>,
+    ) -> Result<crate::Metric, String> {
+        // the given wavelength, or else the one shared by the sources of the sensors
+        let imaging_wavelength = |wavelength: Option<f64>| -> Result<f64, String> {
+            let mut sources = sensors.iter().map(|sensor| wavelengths[sensor.id()]);
+            let first = sources.next().flatten();
+            wavelength
+                .or(first.filter(|_| sources.all(|w| w == first)))
+                .ok_or("no wavelength given, and the sensors don't share a source wavelength")
+                .map_err(str::to_string)
+        };
+        Ok(match self {
+            Metric::WafefrontError => crate::Metric::WavefrontError,
+            Metric::MeasurementVector => crate::Metric::MeasurementVector,
+            Metric::ZernikeProjection { nmodes, radius } => {
+                crate::Metric::new_zernike_projection(nmodes, radius, sensors)?
+            }
+            Metric::ResidualWavefrontError { remove, radius } => {
+                let noll = match remove {
+                    Removal::Piston => vec![1],
+                    Removal::TipTilt => vec![1, 2, 3],
+                    Removal::Focus => vec![1, 2, 3, 4],
+                    Removal::Modes(noll) => noll,
+                };
+                crate::Metric::new_residual_wavefront_error(&noll, radius, sensors)?
+            }
+            Metric::Image {
+                wavelength,
+                oversampling,
+                quantity,
+                psf_prefix,
+            } => {
+                let unit = unit.ok_or("imaging needs a phase unit")?;
+                let wavelength = imaging_wavelength(wavelength)?;
+                if oversampling == 0 {
+                    return Err("oversampling must be at least 1".to_string());
+                }
+                crate::Metric::new_image(
+                    wavelength,
+                    unit.radians_at(wavelength),
+                    oversampling,
+                    match quantity {
+                        ImageQuantity::Strehl => crate::ImageQuantity::Strehl,
+                        ImageQuantity::Fwhm => crate::ImageQuantity::Fwhm,
+                        ImageQuantity::EncircledEnergy(radius) => {
+                            crate::ImageQuantity::EncircledEnergy(radius)
+                        }
+                    },
+                    psf_prefix,
+                    sensors,
+                )?
+            }
+            Metric::ErrorBudget { cross_terms } => crate::Metric::ErrorBudget { cross_terms },
+            Metric::MarechalStrehl { wavelength } => {
+                let unit = unit.ok_or("the Marechal approximation needs a phase unit")?;
+                let wavelength = imaging_wavelength(wavelength)?;
+                crate::Metric::new_marechal_strehl(unit.radians_at(wavelength), sensors)?
+            }
+        })
+    }
 }
 
 impl FromStr for Config {
@@ -120,51 +977,55 @@ impl Config {
         Ok(())
     }
 
-    pub fn to_system(self) -> System {
-        let sys_disturbances: Vec<Arc<crate::Disturbance>> = self
-            .disturbances
-            .into_iter()
-            .map(
-                |Disturbance::Zernike {
-                     id,
-                     coeffs,
-                     radius,
-                     altitude,
-                 }| {
-                    Arc::new(crate::Disturbance::new_zernike(
-                        id, coeffs, radius, altitude,
-                    ))
-                },
-            )
+    pub fn to_system(self) -> Result<System, ConfigError> {
+        let mut sys_disturbances: Vec<Arc<crate::Disturbance>> = vec![];
+        for disturbance in self.disturbances {
+            sys_disturbances.extend(disturbance.into_system(self.unit.as_ref())?);
+        }
+        for path in &self.paths {
+            for element in &path.elements {
+                if let Element::Surface(surface) = element
+                    && !sys_disturbances.iter().any(|d| d.id() == surface)
+                {
+                    return Err(ConfigError::InvalidPath(
+                        path.id.clone(),
+                        format!("unknown disturbance {surface}"),
+                    ));
+                }
+            }
+        }
+        let wavelengths: BTreeMap<String, Option<f64>> = self
+            .sensors
+            .iter()
+            .map(|sensor| {
+                let (id, beam, _) = sensor.common();
+                (id.clone(), beam.wavelength(&self.sources))
+            })
             .collect();
+        let visible = self
+            .sensors
+            .iter()
+            .map(|sensor| {
+                let id = sensor.common().0.clone();
+                sensor
+                    .visible_surfaces(&self.paths, &self.sources)
+                    .map(|surfaces| (id.clone(), surfaces))
+                    .map_err(|msg| ConfigError::InvalidSensor(id, msg))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        let sys_pupil = self.pupil.map(Pupil::into_system).transpose()?;
         let sys_sensors: Vec<Arc<crate::Sensor>> = self
             .sensors
             .into_iter()
-            .map(|sensor| match sensor {
-                Sensor::Shwfs {
-                    id,
-                    nsubx,
-                    subwidth,
-                    centre,
-                    rotation,
-                    direction,
-                    gsalt,
-                } => Arc::new(crate::Sensor::new_shwfs(
-                    &id, nsubx, subwidth, centre, rotation, direction, gsalt,
-                )),
-                Sensor::Imager {
-                    id,
-                    nsamples,
-                    pitch,
-                    centre,
-                    rotation,
-                    direction,
-                    gsalt,
-                } => Arc::new(crate::Sensor::new_imager(
-                    &id, nsamples, pitch, centre, rotation, direction, gsalt,
-                )),
+            .map(|sensor| {
+                let mut sensor =
+                    sensor.into_system(sys_pupil.as_ref(), &self.sources, self.unit.as_ref())?;
+                if let Some(pupil) = &sys_pupil {
+                    sensor.apply_pupil(pupil);
+                }
+                Ok(Arc::new(sensor))
             })
-            .collect();
+            .collect::<Result<_, ConfigError>>()?;
         let sys_outputs: Vec<crate::Output> = self
             .outputs
             .into_iter()
@@ -174,42 +1035,1022 @@ impl Config {
                      sensors,
                      metric,
                      id,
-                 }| crate::Output {
-                    sensors: sys_sensors
+                 }| {
+                    let sensors: Vec<Arc<crate::Sensor>> = sys_sensors
                         .iter()
-                        .filter_map(|p| {
-                            match sensors.contains(match &**p {
-                                crate::Sensor::Shwfs { id, .. } => id,
-                                crate::Sensor::Imager { id, .. } => id,
-                            }) {
-                                true => Some(p.clone()),
-                                false => None,
-                            }
+                        .filter_map(|p| match sensors.contains(p.id()) {
+                            true => Some(p.clone()),
+                            false => None,
                         })
-                        .collect(),
-                    disturbances: sys_disturbances
+                        .collect();
+                    // gain of a disturbance seen by a sensor, `None` if it isn't seen.
+                    // Sensors on an optical path only see the disturbances along it.
+                    let gain = |sensor: &crate::Sensor, id: &String| {
+                        let listed = disturbances.iter().find(|d| d.id() == id);
+                        match &visible[sensor.id()] {
+                            None => listed.map(OutputDisturbance::gain),
+                            Some(surfaces) if surfaces.contains(id) => match listed {
+                                Some(listed) => Some(listed.gain()),
+                                None => disturbances.is_empty().then_some(1.0),
+                            },
+                            Some(_) => None,
+                        }
+                    };
+                    let metric = metric
+                        .into_system(&sensors, self.unit.as_ref(), &wavelengths)
+                        .map_err(|msg| ConfigError::InvalidOutput(id.clone(), msg))?;
+                    let total = crate::BUDGET_TOTAL.to_string();
+                    if let crate::Metric::ErrorBudget { cross_terms } = metric {
+                        if sensors.iter().any(|sensor| gain(sensor, &total).is_some()) {
+                            return Err(ConfigError::InvalidOutput(
+                                id,
+                                format!("{total} is reserved for the total of the budget"),
+                            ));
+                        }
+                        // ids of the budgeted disturbances, in the order of the budget
+                        let ids: BTreeSet<&String> = sensors
+                            .iter()
+                            .flat_map(|sensor| {
+                                sys_disturbances
+                                    .iter()
+                                    .map(|d| d.id())
+                                    .filter(|id| gain(sensor, id).is_some())
+                            })
+                            .collect();
+                        let pairs = ids
+                            .iter()
+                            .enumerate()
+                            .flat_map(|(i, a)| ids.iter().skip(i + 1).map(move |b| (a, b)));
+                        if cross_terms
+                            && let Some(label) = pairs
+                                .map(|(a, b)| crate::cross_term_label(a, b))
+                                .find(|label| ids.contains(label))
+                        {
+                            return Err(ConfigError::InvalidOutput(
+                                id,
+                                format!("{label} is reserved for a cross term of the budget"),
+                            ));
+                        }
+                    }
+                    let disturbances = sensors
                         .iter()
-                        .filter_map(|p| {
-                            match disturbances.contains(match &**p {
-                                crate::Disturbance::Zernike { id, .. } => id,
-                            }) {
-                                true => Some(p.clone()),
-                                false => None,
-                            }
+                        .map(|sensor| {
+                            sys_disturbances
+                                .iter()
+                                .filter_map(|p| match gain(sensor, p.id())? {
+                                    1.0 => Some(p.clone()),
+                                    gain => Some(Arc::new(crate::Disturbance::new_scaled(
+                                        p.clone(),
+                                        gain,
+                                    ))),
+                                })
+                                .collect()
                         })
-                        .collect(),
-                    metric: match metric {
-                        Metric::WafefrontError => crate::Metric::WavefrontError,
-                        Metric::MeasurementVector => crate::Metric::MeasurementVector,
-                    },
-                    id,
+                        .collect();
+                    Ok(crate::Output::new(id, sensors, disturbances, metric))
                 },
             )
-            .collect();
-        System {
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(System {
             outputs: sys_outputs,
+        })
+    }
+}
+
+impl Disturbance {
+    /// Convert to system disturbances, a turbulence profile expands to one
+    /// disturbance per layer, all sharing the profile id.
+    fn into_system(
+        self,
+        unit: Option<&PhaseUnit>,
+    ) -> Result<Vec<Arc<crate::Disturbance>>, ConfigError> {
+        // phase units per radian at a wavelength, for von Karman screens
+        let per_radian =
+            |wavelength: f64| unit.map_or(1.0, |unit| 1.0 / unit.radians_at(wavelength));
+        Ok(match self {
+            Disturbance::Zernike {
+                id,
+                coeffs,
+                convention,
+                radius,
+                altitude,
+                obscuration,
+                outside,
+            } => {
+                let coeffs = coeffs
+                    .to_nm(convention)
+                    .map_err(|msg| ConfigError::InvalidDisturbance(id.clone(), msg))?;
+                vec![Arc::new(crate::Disturbance::new_zernike(
+                    id,
+                    coeffs,
+                    radius,
+                    altitude,
+                    obscuration,
+                    match outside {
+                        Outside::Extrapolate => crate::Outside::Extrapolate,
+                        Outside::Zero => crate::Outside::Zero,
+                        Outside::Error => crate::Outside::Error,
+                        Outside::Clamp => crate::Outside::Clamp,
+                    },
+                ))]
+            }
+            Disturbance::VonKarman {
+                id,
+                r0,
+                wavelength,
+                l0,
+                altitude,
+                size,
+                pitch,
+                seed,
+            } => {
+                check_screen(r0, l0, size, pitch)
+                    .map_err(|msg| ConfigError::InvalidDisturbance(id.clone(), msg))?;
+                vec![Arc::new(crate::Disturbance::new_von_karman(
+                    id,
+                    PhaseScreen::new_von_karman(r0, l0, size, pitch, seed)
+                        .scaled(per_radian(wavelength)),
+                    altitude,
+                    (0.0, 0.0),
+                ))]
+            }
+            Disturbance::Turbulence {
+                id,
+                r0,
+                wavelength,
+                l0,
+                profile,
+                size,
+                pitch,
+                seed,
+                time,
+            } => {
+                let invalid = |msg: String| ConfigError::InvalidDisturbance(id.clone(), msg);
+                check_screen(r0, l0, size, pitch).map_err(invalid)?;
+                let layers = profile.layers()?;
+                if let Some(layer) = layers.iter().find(|layer| layer.weight < 0.0) {
+                    return Err(invalid(format!(
+                        "negative weight of the layer at {} m",
+                        layer.altitude
+                    )));
+                }
+                let total_weight: f64 = layers.iter().map(|layer| layer.weight).sum();
+                if total_weight <= 0.0 {
+                    return Err(invalid("total layer weight must be positive".to_string()));
+                }
+                layers
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, layer)| {
+                        // r0 scales with the integrated Cn2 as r0^(-5/3)
+                        let layer_r0 = r0 * (layer.weight / total_weight).powf(-3.0 / 5.0);
+                        Arc::new(crate::Disturbance::new_von_karman(
+                            id.clone(),
+                            PhaseScreen::new_von_karman(
+                                layer_r0,
+                                l0,
+                                size,
+                                pitch,
+                                seed.wrapping_add(i as u64),
+                            )
+                            .scaled(per_radian(wavelength)),
+                            layer.altitude,
+                            (layer.wind.0 * time, layer.wind.1 * time),
+                        ))
+                    })
+                    .collect()
+            }
+            Disturbance::Dm {
+                id,
+                nact,
+                pitch,
+                altitude,
+                rotation,
+                offset,
+                influence,
+                mut commands,
+            } => {
+                if nact == 0 || pitch <= 0.0 {
+                    return Err(ConfigError::InvalidDisturbance(
+                        id,
+                        format!("nact ({nact}) and pitch ({pitch}) must be positive"),
+                    ));
+                }
+                if let Influence::Gaussian { coupling } = influence
+                    && !(coupling > 0.0 && coupling < 1.0)
+                {
+                    return Err(ConfigError::InvalidDisturbance(
+                        id,
+                        format!("coupling ({coupling}) must be between 0 and 1"),
+                    ));
+                }
+                if commands.is_empty() {
+                    commands = vec![0.0; nact * nact];
+                }
+                if commands.len() != nact * nact {
+                    return Err(ConfigError::InvalidDisturbance(
+                        id,
+                        format!("expected {} commands, got {}", nact * nact, commands.len()),
+                    ));
+                }
+                let influence = match influence {
+                    Influence::Gaussian { coupling } => {
+                        crate::dm::Influence::new_gaussian(coupling)
+                    }
+                    Influence::Bicubic => crate::dm::Influence::Bicubic,
+                };
+                let grid = ActuatorGrid::new(
+                    commands,
+                    nact,
+                    pitch,
+                    rotation.to_radians(),
+                    Vec2D::new(offset.0, offset.1),
+                    influence,
+                );
+                vec![Arc::new(crate::Disturbance::new_dm(id, grid, altitude))]
+            }
+            Disturbance::PhaseMap {
+                id,
+                file,
+                extent,
+                altitude,
+                unit: map_unit,
+                scale,
+                interpolation,
+            } => {
+                let factor = match (map_unit, unit) {
+                    (None, _) => 1.0,
+                    (Some(map_unit), Some(unit)) => map_unit.metres() / unit.metres(),
+                    (Some(_), None) => {
+                        return Err(ConfigError::InvalidDisturbance(
+                            id,
+                            "a map with a unit needs a phase unit to convert it into".to_string(),
+                        ));
+                    }
+                };
+                let values = read_map(&file)? * (factor * scale);
+                let interpolation = match interpolation {
+                    Interpolation::Bilinear => crate::screen::Interpolation::Bilinear,
+                    Interpolation::Bicubic => crate::screen::Interpolation::Bicubic,
+                };
+                let map = PhaseMap::new(values, extent, interpolation);
+                vec![Arc::new(crate::Disturbance::new_phase_map(
+                    id, map, altitude,
+                ))]
+            }
+        })
+    }
+}
+
+/// Check that the parameters of a von Karman phase screen are positive.
+fn check_screen(r0: f64, l0: f64, size: f64, pitch: f64) -> Result<(), String> {
+    for (name, value) in [("r0", r0), ("l0", l0), ("size", size), ("pitch", pitch)] {
+        if value <= 0.0 {
+            return Err(format!("{name} must be positive, got {value}"));
         }
     }
+    Ok(())
 }
 
-impl Disturbance {}
+impl Sensor {
+    /// id, source and optical path, which all sensors have
+    fn common(&self) -> (&String, &Beam, Option<&String>) {
+        match self {
+            Sensor::Shwfs { id, beam, path, .. }
+            | Sensor::Imager { id, beam, path, .. }
+            | Sensor::Pyramid { id, beam, path, .. }
+            | Sensor::ZernikeProjection { id, beam, path, .. }
+            | Sensor::Curvature { id, beam, path, .. }
+            | Sensor::LowOrder { id, beam, path, .. } => (id, beam, path.as_ref()),
+        }
+    }
+
+    /// Ids of the disturbances visible to the sensor along its optical path,
+    /// i.e., those after the entry point of its source, `None` if the sensor
+    /// isn't on a path.
+    fn visible_surfaces(
+        &self,
+        paths: &[Path],
+        sources: &[Source],
+    ) -> Result<Option<Vec<String>>, String> {
+        let (_, beam, path) = self.common();
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        let elements = Path::elements(paths, path)?;
+        let entry = match beam {
+            Beam::Source { source } => sources
+                .iter()
+                .find(|s| &s.id == source)
+                .and_then(|s| s.entry.as_ref()),
+            Beam::Direction { .. } => None,
+        };
+        let start = match entry {
+            None => 0,
+            Some(entry) => {
+                elements
+                    .iter()
+                    .position(
+                        |element| matches!(element, Element::Entry { entry: e } if e == entry),
+                    )
+                    .ok_or(format!("source entry {entry} is not on path {path}"))?
+                    + 1
+            }
+        };
+        Ok(Some(
+            elements[start..]
+                .iter()
+                .filter_map(|element| match element {
+                    Element::Surface(id) => Some(id.clone()),
+                    Element::Entry { .. } => None,
+                })
+                .collect(),
+        ))
+    }
+
+    fn into_system(
+        self,
+        pupil: Option<&crate::Pupil>,
+        sources: &[Source],
+        unit: Option<&PhaseUnit>,
+    ) -> Result<crate::Sensor, ConfigError> {
+        let (id, beam, _) = self.common();
+        let (direction, gsalt, source_flux) = beam
+            .resolve(sources)
+            .map_err(|msg| ConfigError::InvalidSensor(id.clone(), msg))?;
+        if let Sensor::Shwfs { magnification, .. } | Sensor::Imager { magnification, .. } = &self
+            && (magnification.0 <= 0.0 || magnification.1 <= 0.0)
+        {
+            return Err(ConfigError::InvalidSensor(
+                id.clone(),
+                "magnification must be positive".to_string(),
+            ));
+        }
+        Ok(match self {
+            Sensor::Shwfs {
+                id,
+                nsubx,
+                subwidth,
+                layout,
+                centre,
+                rotation,
+                flip_x,
+                flip_y,
+                magnification,
+                beam: _,
+                path: _,
+                min_illumination,
+                estimator,
+                edge_length,
+                edge_separation,
+                npoints,
+                altitude,
+                noise,
+            } => {
+                let edge_length = edge_length.unwrap_or(subwidth);
+                let edge_separation = edge_separation.unwrap_or(subwidth);
+                let step = match estimator {
+                    SlopeEstimator::CentreDifference { step } => step,
+                    SlopeEstimator::TwoEdge | SlopeEstimator::TwoLine => edge_separation,
+                };
+                for (name, value) in [
+                    ("edge_length", edge_length),
+                    ("edge_separation", edge_separation),
+                    ("step", step),
+                    ("npoints", npoints as f64),
+                ] {
+                    if value <= 0.0 {
+                        return Err(ConfigError::InvalidSensor(
+                            id,
+                            format!("{name} must be positive, got {value}"),
+                        ));
+                    }
+                }
+                let estimator = match estimator {
+                    SlopeEstimator::TwoEdge => crate::SlopeEstimator::TwoEdge {
+                        edge_length,
+                        edge_separation,
+                        npoints,
+                        altitude: altitude.unwrap_or(f64::INFINITY),
+                    },
+                    SlopeEstimator::TwoLine => crate::SlopeEstimator::TwoLine {
+                        separation: edge_separation,
+                    },
+                    SlopeEstimator::CentreDifference { .. } => {
+                        crate::SlopeEstimator::TwoLine { separation: step }
+                    }
+                };
+                let layout = layout
+                    .centres(nsubx, subwidth)
+                    .map_err(|msg| ConfigError::InvalidSensor(id.clone(), msg))?;
+                // area of a subaperture in the pupil
+                let area = subwidth.powi(2) / (magnification.0 * magnification.1);
+                let noise = noise
+                    .map(|noise| noise.into_system(source_flux.map(|f| f * area), unit))
+                    .transpose()
+                    .map_err(|msg| ConfigError::InvalidSensor(id.clone(), msg))?;
+                crate::Sensor::new_shwfs(
+                    &id,
+                    layout,
+                    subwidth,
+                    centre,
+                    rotation,
+                    (flip_x, flip_y),
+                    magnification,
+                    direction,
+                    gsalt,
+                    min_illumination,
+                    estimator,
+                    noise,
+                )
+            }
+            Sensor::Imager {
+                id,
+                nsamples,
+                pitch,
+                centre,
+                rotation,
+                flip_x,
+                flip_y,
+                magnification,
+                beam: _,
+                path: _,
+            } => crate::Sensor::new_imager(
+                &id,
+                nsamples,
+                pitch,
+                centre,
+                rotation,
+                (flip_x, flip_y),
+                magnification,
+                direction,
+                gsalt,
+            ),
+            Sensor::Pyramid {
+                id,
+                nsamples,
+                pitch,
+                centre,
+                rotation,
+                beam: _,
+                path: _,
+                modulation,
+                gain,
+                regime,
+            } => {
+                if modulation <= 0.0 {
+                    return Err(ConfigError::InvalidSensor(
+                        id,
+                        "modulation radius must be positive".to_string(),
+                    ));
+                }
+                let Some(unit) = unit else {
+                    return Err(ConfigError::InvalidSensor(
+                        id,
+                        "modulation needs a phase unit, to convert it into slopes".to_string(),
+                    ));
+                };
+                crate::Sensor::new_pyramid(
+                    &id,
+                    nsamples,
+                    pitch,
+                    centre,
+                    rotation,
+                    direction,
+                    gsalt,
+                    modulation * crate::AS2RAD / unit.metres(),
+                    gain,
+                    match regime {
+                        PyramidRegime::Geometric => crate::PyramidRegime::Geometric,
+                        PyramidRegime::Linear => crate::PyramidRegime::Linear,
+                    },
+                )
+            }
+            Sensor::ZernikeProjection {
+                id,
+                order,
+                radius,
+                nsamples,
+                beam: _,
+                path: _,
+            } => {
+                let nmodes = (order + 1) * (order + 2) / 2;
+                crate::Sensor::new_zernike_projection(
+                    &id, nmodes, radius, nsamples, direction, gsalt, pupil,
+                )
+                .ok_or(ConfigError::InvalidSensor(
+                    id,
+                    format!("too few samples to fit {nmodes} zernike modes"),
+                ))?
+            }
+            Sensor::Curvature {
+                id,
+                nsubx,
+                subwidth,
+                layout,
+                centre,
+                rotation,
+                beam: _,
+                path: _,
+                focal_length,
+                extra_focal,
+                npoints,
+                min_illumination,
+            } => {
+                if extra_focal <= 0.0 || extra_focal >= focal_length || npoints == 0 {
+                    return Err(ConfigError::InvalidSensor(
+                        id,
+                        "need 0 < extra_focal < focal_length and npoints > 0".to_string(),
+                    ));
+                }
+                let Some(unit) = unit else {
+                    return Err(ConfigError::InvalidSensor(
+                        id,
+                        "curvature needs a phase unit, to convert the phase into metres"
+                            .to_string(),
+                    ));
+                };
+                let layout = layout
+                    .centres(nsubx, subwidth)
+                    .map_err(|msg| ConfigError::InvalidSensor(id.clone(), msg))?;
+                // the defocused images are equivalent to propagating the pupil
+                // by this distance either side of it
+                let distance = focal_length * (focal_length - extra_focal) / extra_focal;
+                crate::Sensor::new_curvature(
+                    &id,
+                    layout,
+                    subwidth,
+                    centre,
+                    rotation,
+                    direction,
+                    gsalt,
+                    distance,
+                    unit.metres(),
+                    npoints,
+                    min_illumination,
+                    pupil,
+                )
+            }
+            Sensor::LowOrder {
+                id,
+                nsubx,
+                subwidth,
+                centre,
+                rotation,
+                beam: _,
+                path: _,
+                density,
+                min_illumination,
+                tolerance,
+                noise,
+            } => {
+                let noise = noise
+                    .map(|noise| noise.into_system(source_flux.map(|f| f * subwidth.powi(2)), unit))
+                    .transpose()
+                    .map_err(|msg| ConfigError::InvalidSensor(id.clone(), msg))?;
+                crate::Sensor::new_low_order(
+                    &id,
+                    nsubx,
+                    subwidth,
+                    centre,
+                    rotation,
+                    direction,
+                    gsalt,
+                    density.max(1),
+                    min_illumination,
+                    tolerance,
+                    noise,
+                    pupil,
+                )
+                .map_err(|msg| ConfigError::InvalidSensor(id, msg))?
+            }
+        })
+    }
+}
+
+impl Path {
+    /// All elements along a path, starting from the root of the path that it
+    /// branches off from.
+    fn elements<'a>(paths: &'a [Path], id: &str) -> Result<Vec<&'a Element>, String> {
+        let mut chain: Vec<&Path> = vec![];
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let path = paths
+                .iter()
+                .find(|path| path.id == id)
+                .ok_or(format!("unknown path {id}"))?;
+            if chain.len() == paths.len() {
+                return Err(format!("path {id} branches off from itself"));
+            }
+            chain.push(path);
+            next = path.from.as_deref();
+        }
+        Ok(chain.iter().rev().flat_map(|path| &path.elements).collect())
+    }
+}
+
+impl Pupil {
+    fn into_system(self) -> Result<crate::Pupil, ConfigError> {
+        let n = self.spiders;
+        let spider_angles: Vec<f64> = if self.spider_angles.is_empty() {
+            (0..n).map(|i| i as f64 * 360.0 / n as f64).collect()
+        } else if n == 0 || n == self.spider_angles.len() {
+            self.spider_angles
+        } else {
+            return Err(ConfigError::InvalidPupil(format!(
+                "{n} spiders but {} spider angles",
+                self.spider_angles.len()
+            )));
+        };
+        let mask = match self.mask {
+            Some(file) => Some(PhaseMap::new(
+                read_map(&file)?,
+                (self.diameter, self.diameter),
+                crate::screen::Interpolation::Bilinear,
+            )),
+            None => None,
+        };
+        Ok(crate::Pupil::new(
+            self.diameter,
+            self.obscuration,
+            self.spider_width,
+            spider_angles.into_iter().map(f64::to_radians).collect(),
+            mask,
+            self.flag_masked,
+        ))
+    }
+}
+
+impl Coefficients {
+    /// pair each coefficient with the `(n, m)` of its mode
+    fn to_nm(&self, convention: Convention) -> Result<Vec<NmCoefficient>, String> {
+        match self {
+            Coefficients::Dense(values) => values
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| {
+                    let j = convention.first_index() + i as u32;
+                    convention
+                        .index_to_nm(j)
+                        .map(|nm| (nm, value))
+                        .ok_or(format!("invalid {convention:?} index {j}"))
+                })
+                .collect(),
+            Coefficients::Sparse(values) => values
+                .iter()
+                .map(|coefficient| match *coefficient {
+                    // any valid mode, whichever the convention
+                    Coefficient::Nm { n, m, value } => Convention::Noll
+                        .nm_to_index(n, m)
+                        .map(|_| ((n, m), value))
+                        .ok_or(format!("invalid zernike mode (n={n}, m={m})")),
+                    Coefficient::J { j, value } => convention
+                        .index_to_nm(j)
+                        .map(|nm| (nm, value))
+                        .ok_or(format!("invalid {convention:?} index {j}")),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Profile {
+    fn layers(self) -> Result<Vec<Layer>, ConfigError> {
+        match self {
+            Profile::Layers(layers) => Ok(layers),
+            Profile::File(filename) => {
+                let invalid = |msg: &str| ConfigError::Profile(filename.clone(), msg.to_string());
+                let contents = fs::read_to_string(&filename)?;
+                let mut layers = vec![];
+                for line in contents.lines() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let values = line
+                        .split(|c: char| c.is_whitespace() || c == ',')
+                        .filter(|v| !v.is_empty())
+                        .map(|v| v.parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                        .map_err(|_| invalid(&format!("could not parse line \"{line}\"")))?;
+                    let [altitude, weight, speed, direction] = values[..] else {
+                        return Err(invalid(&format!("expected 4 columns in line \"{line}\"")));
+                    };
+                    let direction = direction.to_radians();
+                    layers.push(Layer {
+                        altitude,
+                        weight,
+                        wind: (speed * direction.cos(), speed * direction.sin()),
+                    });
+                }
+                if layers.is_empty() {
+                    return Err(invalid("no layers found"));
+                }
+                Ok(layers)
+            }
+        }
+    }
+}
+
+/// Read `x, y` positions from a text file, one pair per line, separated by
+/// commas or whitespace. Lines starting with `#` are ignored.
+fn read_positions(filename: &str) -> Result<Vec<Vec2D>, String> {
+    let contents = fs::read_to_string(filename).map_err(|e| format!("{filename}: {e}"))?;
+    let mut positions = vec![];
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("{filename}: could not parse line \"{line}\""))?;
+        let [x, y] = values[..] else {
+            return Err(format!("{filename}: expected 2 columns in line \"{line}\""));
+        };
+        positions.push(Vec2D::new(x, y));
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(json: &str) -> Result<System, ConfigError> {
+        Config::from_str(json)?.to_system()
+    }
+
+    /// sources in the sky and in the calibration unit, looking through a DSM
+    /// that is before the calibration unit and an ADC that is after it
+    fn calibration_config(entry: &str) -> String {
+        format!(
+            r#"{{
+                "sources": [
+                    {{"id": "sky", "direction": [0.0, 0.0], "altitude": 1e20, "wavelength": 1e-6}},
+                    {{"id": "cal", "direction": [0.0, 0.0], "altitude": 10.0, "wavelength": 1e-6,
+                        "entry": "{entry}"}}
+                ],
+                "paths": [
+                    {{"id": "common", "elements": ["dsm", {{"entry": "calibration unit"}}, "adc"]}}
+                ],
+                "disturbances": [
+                    {{"Zernike": {{"id": "dsm", "coeffs": [0.0, 1.0, 2.0], "radius": 4.0,
+                        "altitude": 0.0}}}},
+                    {{"Zernike": {{"id": "adc", "coeffs": [0.0, 0.0, 0.0, 0.5], "radius": 4.0,
+                        "altitude": 0.0}}}}
+                ],
+                "sensors": [
+                    {{"Imager": {{"id": "sky imager", "source": "sky", "path": "common",
+                        "nsamples": 4, "pitch": 1.0, "centre": [0.0, 0.0], "rotation": 0.0}}}},
+                    {{"Imager": {{"id": "cal imager", "source": "cal", "path": "common",
+                        "nsamples": 4, "pitch": 1.0, "centre": [0.0, 0.0], "rotation": 0.0}}}}
+                ],
+                "outputs": [
+                    {{"id": "sky", "disturbances": ["dsm"], "sensors": ["sky imager"],
+                        "metric": "MeasurementVector"}},
+                    {{"id": "cal", "disturbances": ["dsm"], "sensors": ["cal imager"],
+                        "metric": "MeasurementVector"}}
+                ]
+            }}"#
+        )
+    }
+
+    #[test]
+    fn calibration_source_skips_dsm() {
+        let results = system(&calibration_config("calibration unit"))
+            .unwrap()
+            .evaluate()
+            .unwrap()
+            .results;
+        let [sky, cal] = &results[..] else {
+            panic!("expected two results");
+        };
+        assert!(sky.values.iter().any(|v| v.abs() > 1e-3));
+        assert_eq!(cal.values.len(), sky.values.len());
+        assert!(cal.values.iter().all(|v| *v == 0.0), "{:?}", cal.values);
+    }
+
+    /// an imager seeing zernike disturbances with the given ids and
+    /// coefficients, budgeted with cross terms
+    fn budget_config(disturbances: &[(&str, [f64; 4])]) -> String {
+        let ids: Vec<String> = disturbances
+            .iter()
+            .map(|(id, _)| format!("{id:?}"))
+            .collect();
+        let disturbances: Vec<String> = disturbances
+            .iter()
+            .map(|(id, coeffs)| {
+                format!(
+                    r#"{{"Zernike": {{"id": "{id}", "coeffs": {coeffs:?}, "radius": 2.0,
+                        "altitude": 0.0}}}}"#
+                )
+            })
+            .collect();
+        format!(
+            r#"{{
+                "disturbances": [{}],
+                "sensors": [
+                    {{"Imager": {{"id": "imager", "nsamples": 8, "pitch": 0.5, "centre": [0.0, 0.0],
+                        "rotation": 0.0, "direction": [0.0, 0.0], "gsalt": 1e20}}}}
+                ],
+                "outputs": [
+                    {{"id": "budget", "disturbances": [{}], "sensors": ["imager"],
+                        "metric": {{"ErrorBudget": {{"cross_terms": true}}}}}}
+                ]
+            }}"#,
+            disturbances.join(", "),
+            ids.join(", ")
+        )
+    }
+
+    #[test]
+    fn budget_signed_squares_add_up() {
+        let config = budget_config(&[
+            ("a", [0.0, 1.0, 0.5, 0.2]),
+            ("b", [0.0, -0.4, 0.3, 0.0]),
+            ("c", [0.0, 0.0, -0.6, 0.7]),
+        ]);
+        let results = system(&config).unwrap().evaluate().unwrap().results;
+        let budget = &results[0].budget;
+        // the three disturbances, three cross terms and the total
+        assert_eq!(budget.len(), 7, "{budget:?}");
+        let total = budget[crate::BUDGET_TOTAL][0];
+        let sum: f64 = budget
+            .iter()
+            .filter(|(label, _)| label.as_str() != crate::BUDGET_TOTAL)
+            .map(|(_, value)| value[0] * value[0].abs())
+            .sum();
+        assert!(budget.values().any(|value| value[0] < 0.0));
+        assert!((sum - total.powi(2)).abs() < 1e-9, "{sum} {total}");
+    }
+
+    #[test]
+    fn cross_term_label_rejected() {
+        let config = budget_config(&[
+            ("a", [0.0, 1.0, 0.0, 0.0]),
+            ("b", [0.0, 0.0, 1.0, 0.0]),
+            ("a x b", [0.0, 0.0, 0.0, 1.0]),
+        ]);
+        let result = system(&config);
+        assert!(matches!(result, Err(ConfigError::InvalidOutput(id, _)) if id == "budget"));
+    }
+
+    #[test]
+    fn phase_map_unit_converted() {
+        let filename = std::env::temp_dir()
+            .join(format!("rao-forward-{}-map.npy", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        crate::mapfile::write_npy(&filename, &ndarray::Array2::from_elem((4, 4), 250.0)).unwrap();
+        let config = |unit: &str| {
+            format!(
+                r#"{{
+                    {unit}
+                    "disturbances": [
+                        {{"PhaseMap": {{"id": "map", "file": "{filename}", "extent": [4.0, 4.0],
+                            "altitude": 0.0, "unit": "Nanometres", "scale": -2.0}}}}
+                    ],
+                    "sensors": [
+                        {{"Imager": {{"id": "imager", "nsamples": 2, "pitch": 1.0,
+                            "centre": [0.0, 0.0], "rotation": 0.0, "direction": [0.0, 0.0],
+                            "gsalt": 1e20}}}}
+                    ],
+                    "outputs": [
+                        {{"id": "map", "disturbances": ["map"], "sensors": ["imager"],
+                            "metric": "MeasurementVector"}}
+                    ]
+                }}"#
+            )
+        };
+        let results = system(&config(r#""unit": "Micrometres","#))
+            .map(|system| system.evaluate().unwrap().results);
+        let unitless = system(&config(""));
+        std::fs::remove_file(&filename).unwrap();
+        assert_eq!(results.unwrap()[0].values, vec![-0.5; 4]);
+        assert!(matches!(unitless, Err(ConfigError::InvalidDisturbance(id, _)) if id == "map"));
+    }
+
+    /// only the lines of valid subapertures are counted outside the support
+    #[test]
+    fn outside_support_of_valid_subapertures() {
+        let config = r#"{
+            "pupil": {"diameter": 4.0},
+            "disturbances": [
+                {"Zernike": {"id": "tilt", "coeffs": [0.0, 1.0], "radius": 3.0, "altitude": 0.0,
+                    "outside": "Zero"}}
+            ],
+            "sensors": [
+                {"Shwfs": {"id": "wfs", "nsubx": 8, "subwidth": 1.0, "centre": [0.0, 0.0],
+                    "rotation": 0.0, "direction": [0.0, 0.0], "gsalt": 1e20}}
+            ],
+            "outputs": [
+                {"id": "slopes", "disturbances": ["tilt"], "sensors": ["wfs"],
+                    "metric": "MeasurementVector"}
+            ]
+        }"#;
+        let system = system(config).unwrap();
+        let output = &system.outputs[0];
+        assert!(output.sensors[0].valid_measurements().len() < 2 * 64);
+        assert_eq!(output.outside_support["tilt"], 0);
+    }
+
+    /// defocus `c (2 r^2 / R^2 - 1)` has a Laplacian of `8 c / R^2` everywhere
+    #[test]
+    fn curvature_of_defocus() {
+        let config = |unit: &str, coeff: f64| {
+            format!(
+                r#"{{
+                    "unit": "{unit}",
+                    "disturbances": [
+                        {{"Zernike": {{"id": "focus", "coeffs": [0.0, 0.0, 0.0, {coeff}],
+                            "radius": 4.0, "altitude": 0.0}}}}
+                    ],
+                    "sensors": [
+                        {{"Curvature": {{"id": "cwfs", "layout": "Hexagonal",
+                            "nsubx": 5, "subwidth": 1.0, "centre": [0.0, 0.0], "rotation": 0.0,
+                            "focal_length": 10.0, "extra_focal": 1.0, "direction": [0.0, 0.0],
+                            "gsalt": 1e20}}}}
+                    ],
+                    "outputs": [
+                        {{"id": "signals", "sensors": ["cwfs"], "disturbances": ["focus"],
+                            "metric": "MeasurementVector"}}
+                    ]
+                }}"#
+            )
+        };
+        let signals = |unit: &str, coeff: f64| {
+            let results = system(&config(unit, coeff)).unwrap().evaluate().unwrap();
+            results.results[0].values.clone()
+        };
+        let metres = signals("Metres", 1e-6);
+        let micrometres = signals("Micrometres", 1.0);
+        assert_eq!(metres.len(), crate::hexagonal_grid(5, 1.0).len());
+        // propagation distance times the Laplacian
+        let expected = 10.0 * (10.0 - 1.0) / 1.0 * 8e-6 / 4.0f64.powi(2);
+        for (a, b) in metres.iter().zip(&micrometres) {
+            assert!((a.abs() / expected - 1.0).abs() < 1e-9, "{a} {expected}");
+            assert!((a / b - 1.0).abs() < 1e-9, "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn eso_profile_layers() {
+        let filename = std::env::temp_dir()
+            .join(format!("rao-forward-{}-profile.txt", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let profile = "# altitude, weight, speed, direction\n\
+                       0 0.5 10 0\n\
+                       5000, 0.3, 20, 90\n\
+                       \n\
+                       12000 0.2 5 180\n";
+        std::fs::write(&filename, profile).unwrap();
+        let turbulence: Disturbance = serde_json::from_str(&format!(
+            r#"{{"Turbulence": {{"id": "atmosphere", "r0": 0.1, "l0": 25.0,
+                "profile": {{"File": "{filename}"}}, "size": 4.0, "pitch": 0.25,
+                "seed": {}, "time": 2.0}}}}"#,
+            u64::MAX
+        ))
+        .unwrap();
+        let layers = turbulence.into_system(None);
+        std::fs::remove_file(&filename).unwrap();
+        let layers = layers.unwrap();
+        assert_eq!(layers.len(), 3);
+        // altitude, weight, wind vector and seed of each layer
+        let expected: [(f64, f64, (f64, f64), u64); 3] = [
+            (0.0, 0.5, (10.0, 0.0), u64::MAX),
+            (5000.0, 0.3, (0.0, 20.0), 0),
+            (12000.0, 0.2, (-5.0, 0.0), 1),
+        ];
+        for (layer, (altitude, weight, wind, seed)) in layers.iter().zip(expected) {
+            let crate::Disturbance::VonKarman {
+                id,
+                screen,
+                altitude: layer_altitude,
+                shift,
+            } = layer.as_ref()
+            else {
+                panic!("expected a von Karman layer");
+            };
+            assert_eq!(id, "atmosphere");
+            assert_eq!(*layer_altitude, altitude);
+            assert!((shift.x - 2.0 * wind.0).abs() < 1e-9 && (shift.y - 2.0 * wind.1).abs() < 1e-9);
+            // each layer has its share of the integrated r0
+            let r0 = 0.1 * weight.powf(-3.0 / 5.0);
+            let reference = PhaseScreen::new_von_karman(r0, 25.0, 4.0, 0.25, seed);
+            for pos in [Vec2D::new(0.1, -0.3), Vec2D::new(1.3, 0.7)] {
+                assert_eq!(screen.interpolate(&pos), reference.interpolate(&pos));
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_entry_rejected() {
+        let result = system(&calibration_config("telescope simulator"));
+        assert!(matches!(result, Err(ConfigError::InvalidSensor(id, _)) if id == "cal imager"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Deformable mirror model: a square grid of actuators, each contributing its
+//! command times an influence function to the mirror surface.
+
+use rao::Vec2D;
+
+use crate::screen::keys_cubic;
+
+/// Influence function of a single actuator, in units of actuator pitch.
+pub(crate) enum Influence {
+    /// Gaussian influence function, see [`rao::coupling_to_sigma`].
+    Gaussian {
+        /// standard deviation (in actuator pitches)
+        sigma: f64,
+    },
+    /// Separable bicubic spline (Keys cubic convolution kernel), which is 1.0
+    /// at the actuator and 0.0 at all of its neighbours.
+    Bicubic,
+}
+
+impl Influence {
+    pub(crate) fn new_gaussian(coupling: f64) -> Self {
+        Self::Gaussian {
+            sigma: rao::coupling_to_sigma(coupling, 1.0),
+        }
+    }
+
+    /// distance (in pitches) beyond which the influence is negligible
+    fn reach(&self) -> f64 {
+        match self {
+            // gaussian(5.0) < 4e-6
+            Self::Gaussian { sigma } => 5.0 * sigma,
+            Self::Bicubic => 2.0,
+        }
+    }
+
+    fn eval(&self, dx: f64, dy: f64) -> f64 {
+        match self {
+            Self::Gaussian { sigma } => rao::utils::gaussian(dx.hypot(dy) / sigma),
+            Self::Bicubic => keys_cubic(dx) * keys_cubic(dy),
+        }
+    }
+}
+
+/// Square `nact` by `nact` grid of actuators, centred on `offset` and rotated
+/// by `rotation` (radians) about it.
+pub(crate) struct ActuatorGrid {
+    /// actuator commands, row-major (x fastest), length `nact * nact`
+    commands: Vec<f64>,
+    nact: usize,
+    /// actuator pitch in metres (at the conjugate altitude)
+    pitch: f64,
+    rotation: f64,
+    offset: Vec2D,
+    influence: Influence,
+}
+
+impl ActuatorGrid {
+    pub(crate) fn new(
+        commands: Vec<f64>,
+        nact: usize,
+        pitch: f64,
+        rotation: f64,
+        offset: Vec2D,
+        influence: Influence,
+    ) -> Self {
+        Self {
+            commands,
+            nact,
+            pitch,
+            rotation,
+            offset,
+            influence,
+        }
+    }
+
+    /// Whether a position (in metres) is within the footprint of the grid,
+    /// i.e., within half a pitch of the outermost actuators.
+    pub(crate) fn contains(&self, pos: &Vec2D) -> bool {
+        let d = pos - &self.offset;
+        let (sin, cos) = self.rotation.sin_cos();
+        let half_width = self.nact as f64 * self.pitch / 2.0;
+        (d.x * cos + d.y * sin).abs() <= half_width && (-d.x * sin + d.y * cos).abs() <= half_width
+    }
+
+    /// Mirror surface at a position (in metres) in the conjugate plane.
+    pub(crate) fn surface(&self, pos: &Vec2D) -> f64 {
+        // transform into (fractional) actuator indices
+        let d = pos - &self.offset;
+        let (sin, cos) = self.rotation.sin_cos();
+        let centre = (self.nact as f64 - 1.0) / 2.0;
+        let u = (d.x * cos + d.y * sin) / self.pitch + centre;
+        let v = (-d.x * sin + d.y * cos) / self.pitch + centre;
+        let reach = self.influence.reach();
+        let last = self.nact as f64 - 1.0;
+        let range = |w: f64| {
+            let start = (w - reach).ceil().max(0.0);
+            let stop = (w + reach).floor().min(last);
+            start as usize..=stop as usize
+        };
+        if u + reach < 0.0 || v + reach < 0.0 || u - reach > last || v - reach > last {
+            return 0.0;
+        }
+        let mut value = 0.0;
+        for iy in range(v) {
+            for ix in range(u) {
+                let command = self.commands[iy * self.nact + ix];
+                if command != 0.0 {
+                    value += command * self.influence.eval(u - ix as f64, v - iy as f64);
+                }
+            }
+        }
+        value
+    }
+}
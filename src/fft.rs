@@ -0,0 +1,55 @@
+//! Small radix-2 FFT, enough for building phase screens and images on
+//! power-of-two grids without an external dependency.
+
+use core::f64;
+use ndarray::{Array2, Axis};
+use num_complex::Complex64;
+
+/// In-place 1D FFT of a power-of-two length buffer.
+///
+/// `inverse` flips the sign of the exponent, no normalisation is applied in
+/// either direction.
+pub(crate) fn fft(buffer: &mut [Complex64], inverse: bool) {
+    let n = buffer.len();
+    assert!(n.is_power_of_two(), "fft length must be a power of two");
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let w = Complex64::from_polar(1.0, sign * 2.0 * f64::consts::PI / len as f64);
+        for chunk in buffer.chunks_mut(len) {
+            let mut wk = Complex64::new(1.0, 0.0);
+            let (lo, hi) = chunk.split_at_mut(len / 2);
+            for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+                let t = *b * wk;
+                *b = *a - t;
+                *a += t;
+                wk *= w;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// In-place 2D FFT (rows then columns) of a square power-of-two array.
+pub(crate) fn fft2(array: &mut Array2<Complex64>, inverse: bool) {
+    for axis in [Axis(0), Axis(1)] {
+        for mut lane in array.lanes_mut(axis) {
+            let mut buffer = lane.to_vec();
+            fft(&mut buffer, inverse);
+            lane.iter_mut().zip(buffer).for_each(|(a, b)| *a = b);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Fourier optics imaging of a sampled pupil field.
+//!
+//! The complex field `A exp(i phi)` over a grid of pupil samples is zero
+//! padded by (at least) the oversampling factor to a square power-of-two
+//! grid, and Fourier transformed. The PSF is the squared modulus, with zero
+//! spatial frequency moved to pixel `[[n/2, n/2]]`, normalised to a total of
+//! one.
+
+use ndarray::Array2;
+use num_complex::Complex64;
+
+use crate::{AS2RAD, fft};
+
+pub(crate) struct Psf {
+    /// normalised PSF, indexed `[[iy, ix]]`
+    pub(crate) image: Array2<f64>,
+    /// angular size of a pixel along x and y (in arcsec)
+    pixel_scale: (f64, f64),
+    /// peak of the normalised PSF of the same pupil without aberrations
+    diffraction_peak: f64,
+}
+
+impl Psf {
+    /// `amplitude` and `phase` (in radians) of each pupil sample, indexed
+    /// `[[iy, ix]]`, where the samples are `pitch` metres apart along x and y.
+    pub(crate) fn new(
+        amplitude: &Array2<f64>,
+        phase: &Array2<f64>,
+        pitch: (f64, f64),
+        wavelength: f64,
+        oversampling: usize,
+    ) -> Self {
+        let (ny, nx) = amplitude.dim();
+        let n = (ny.max(nx) * oversampling).next_power_of_two();
+        let mut field = Array2::<Complex64>::zeros((n, n));
+        for ((iy, ix), a) in amplitude.indexed_iter() {
+            field[[iy, ix]] = Complex64::from_polar(*a, phase[[iy, ix]]);
+        }
+        fft::fft2(&mut field, false);
+        let mut image = Array2::<f64>::zeros((n, n));
+        for ((iy, ix), c) in field.indexed_iter() {
+            image[[(iy + n / 2) % n, (ix + n / 2) % n]] = c.norm_sqr();
+        }
+        // by Parseval's theorem, the total is n^2 sum(A^2) for any phase,
+        // while the unaberrated peak is sum(A)^2
+        let total = image.sum();
+        let diffraction_peak = amplitude.sum().powi(2) / total;
+        Self {
+            image: image / total,
+            pixel_scale: (
+                wavelength / (n as f64 * pitch.0) / AS2RAD,
+                wavelength / (n as f64 * pitch.1) / AS2RAD,
+            ),
+            diffraction_peak,
+        }
+    }
+
+    /// index and value of the brightest pixel
+    fn peak(&self) -> ((usize, usize), f64) {
+        self.image
+            .indexed_iter()
+            .fold(((0, 0), f64::NEG_INFINITY), |acc, (index, v)| {
+                if *v > acc.1 { (index, *v) } else { acc }
+            })
+    }
+
+    /// Peak of the PSF relative to the unaberrated peak, wherever the peak
+    /// is (so tip-tilt doesn't reduce the Strehl ratio).
+    pub(crate) fn strehl(&self) -> f64 {
+        self.peak().1 / self.diffraction_peak
+    }
+
+    /// Full width at half maximum (in arcsec), as the diameter of a disk
+    /// with the same area as the pixels above half of the peak.
+    pub(crate) fn fwhm(&self) -> f64 {
+        let half = self.peak().1 / 2.0;
+        let count = self.image.iter().filter(|v| **v >= half).count();
+        let area = count as f64 * self.pixel_scale.0 * self.pixel_scale.1;
+        2.0 * (area / std::f64::consts::PI).sqrt()
+    }
+
+    /// Fraction of the energy within `radius` (in arcsec) of the peak.
+    pub(crate) fn encircled_energy(&self, radius: f64) -> f64 {
+        let ((py, px), _) = self.peak();
+        self.image
+            .indexed_iter()
+            .filter(|((iy, ix), _)| {
+                let dx = (*ix as f64 - px as f64) * self.pixel_scale.0;
+                let dy = (*iy as f64 - py as f64) * self.pixel_scale.1;
+                dx.hypot(dy) <= radius
+            })
+            .map(|(_, v)| v)
+            .sum()
+    }
+}
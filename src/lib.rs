@@ -1,18 +1,47 @@
 pub mod config;
+mod annular;
+mod dm;
+mod fft;
+mod imaging;
+mod linalg;
+mod mapfile;
+mod noise;
+mod random;
+mod screen;
+mod zernike_index;
 
 use core::f64;
-use std::sync::Arc;
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
 use thiserror::Error;
 
 pub use config::Config;
+use dm::ActuatorGrid;
+use imaging::Psf;
+use ndarray::{Array1, Array2};
+use noise::NoiseModel;
 use rao::{Line, Measurement, Sampleable, Sampler, Vec2D, Vec3D};
+use screen::{PhaseMap, PhaseScreen};
 use serde::{Deserialize, Serialize};
+use zernike_index::NmCoefficient;
 
 #[derive(Error, Debug)]
 pub enum ResultsError {
     #[error("could not serialize results output")]
     Serialization(#[from] serde_json::Error),
+    #[error("could not write results file")]
+    Io(#[from] std::io::Error),
+    #[error("{count} sample lines of output {output} fall outside {disturbance}")]
+    OutsideSupport {
+        output: String,
+        disturbance: String,
+        count: usize,
+    },
 }
 
 const AS2RAD: f64 = f64::consts::PI / 180.0 / 3600.0;
@@ -23,7 +52,8 @@ pub struct System {
 
 enum Disturbance {
     Zernike {
-        /// indicies to interact with zernike module
+        /// Noll (j, n, |m|) indices of each coefficient, to interact with the
+        /// zernike module
         jnm: Vec<(u32, u32, u32)>,
         /// id must be unique per config file
         id: String,
@@ -33,49 +63,481 @@ enum Disturbance {
         radius: f64,
         /// altitude
         altitude: f64,
+        /// annular radial polynomials of each mode, `None` for the circular
+        /// zernike basis
+        annular: Option<Vec<Vec<f64>>>,
+        /// central obscuration ratio (inner edge of the support)
+        obscuration: f64,
+        /// behaviour outside of the basis support
+        outside: Outside,
     },
+    VonKarman {
+        /// id must be unique per config file
+        id: String,
+        /// seeded random phase screen (in the phase unit)
+        screen: PhaseScreen,
+        /// altitude
+        altitude: f64,
+        /// translation of the screen (in metres), e.g., due to wind
+        shift: Vec2D,
+    },
+    Dm {
+        /// id must be unique per config file
+        id: String,
+        /// actuator layout, influence functions and commands
+        grid: ActuatorGrid,
+        /// conjugation altitude
+        altitude: f64,
+    },
+    PhaseMap {
+        /// id must be unique per config file
+        id: String,
+        /// gridded phase map, e.g., a measured surface
+        map: PhaseMap,
+        /// conjugation altitude
+        altitude: f64,
+    },
+    /// Another disturbance (sharing its id) scaled by a signed gain, e.g.,
+    /// -2.0 for a mirror correcting an aberration in reflection.
+    Scaled {
+        gain: f64,
+        disturbance: Arc<Disturbance>,
+    },
+}
+/// Behaviour of a basis-defined disturbance when sampled outside its support.
+enum Outside {
+    /// evaluate the polynomials anyway
+    Extrapolate,
+    /// return zero
+    Zero,
+    /// return NaN, and fail the evaluation of any output that samples there
+    Error,
+    /// evaluate at the nearest point of the support (same azimuth)
+    Clamp,
 }
+
 enum Sensor {
     Shwfs {
         id: String,
         measurements: Vec<Measurement>,
+        /// false for measurements flagged as masked by the pupil
+        valid: Vec<bool>,
+        /// centre of each lenslet in the pupil plane, in layout order
+        lenslets: Vec<Vec2D>,
+        /// subaperture width (in metres, in the sensor frame)
+        subwidth: f64,
+        /// orientation of the lenslet array
+        frame: Frame,
+        /// minimum fraction of a subaperture that the pupil must illuminate
+        /// for the subaperture to be valid
+        min_illumination: f64,
+        /// valid-subaperture map, one entry per lenslet in layout order
+        subapertures: Vec<bool>,
+        noise: Option<NoiseModel>,
+        /// noise standard deviation of each measurement
+        sigma: Vec<f64>,
     },
     Imager {
         id: String,
         measurements: Vec<Measurement>,
+        /// false for measurements flagged as masked by the pupil
+        valid: Vec<bool>,
+        /// number of samples across the square grid of the imager
+        nsamples: usize,
+        /// distance between adjacent samples along the imager x and y axes
+        /// (in metres, wrt pupil)
+        pitch: (f64, f64),
+        /// grid index (row-major, x fastest) of each measurement
+        indices: Vec<usize>,
+    },
+    /// Modulated pyramid WFS in the geometric optics approximation. The
+    /// measurements are the local x and y slopes at each pupil sample, which
+    /// are converted to pyramid signals by [`Sensor::signal`].
+    Pyramid {
+        id: String,
+        measurements: Vec<Measurement>,
+        /// false for measurements flagged as masked by the pupil
+        valid: Vec<bool>,
+        /// modulation radius, in the same units as the slopes
+        modulation: f64,
+        /// optical gain applied to the signals
+        gain: f64,
+        regime: PyramidRegime,
+    },
+    /// Least-squares fit of zernike modes to the phase sampled over a
+    /// circular pupil, reporting one coefficient per mode.
+    ZernikeProjection {
+        id: String,
+        /// phase samples within the pupil
+        measurements: Vec<Measurement>,
+        /// Noll index of each fitted mode
+        noll: Vec<u32>,
+        /// maps phase samples to mode coefficients
+        reconstructor: Array2<f64>,
     },
+    /// Curvature WFS in the geometric optics approximation, reporting the
+    /// normalised intensity difference `(I1 - I2) / (I1 + I2)` of each
+    /// subaperture. That is the propagation distance times the Laplacian
+    /// inside the pupil minus the normal derivative at the pupil edge, which
+    /// (by the divergence theorem) is the outward flux of the wavefront
+    /// gradient through the sides of the subaperture that lie within the
+    /// pupil, divided by the illuminated area of the subaperture.
+    Curvature {
+        id: String,
+        /// normal derivatives along short segments of subaperture sides
+        measurements: Vec<Measurement>,
+        /// signal index and weight of each measurement
+        weights: Vec<(usize, f64)>,
+        /// number of signals, i.e., of valid subapertures
+        nsignals: usize,
+        /// valid-subaperture map, one entry per subaperture in layout order
+        subapertures: Vec<bool>,
+    },
+    /// Low-order (e.g., tip-tilt) WFS with large subapertures, reporting the
+    /// gradient averaged over the illuminated part of each subaperture: the
+    /// x gradients of every valid subaperture, then the y gradients.
+    LowOrder {
+        id: String,
+        /// slopes across the illuminated cells of every subaperture
+        measurements: Vec<Measurement>,
+        /// signal index and weight of each measurement
+        weights: Vec<(usize, f64)>,
+        /// number of signals, i.e., twice the number of valid subapertures
+        nsignals: usize,
+        /// valid-subaperture map, one entry per subaperture in layout order
+        subapertures: Vec<bool>,
+        noise: Option<NoiseModel>,
+        /// noise standard deviation of each signal
+        sigma: Vec<f64>,
+    },
+}
+
+/// How the slopes of a subaperture are estimated from the phase.
+enum SlopeEstimator {
+    /// average difference between two opposite edges of a rectangle, see
+    /// [`Measurement::SlopeTwoEdge`]
+    TwoEdge {
+        edge_length: f64,
+        edge_separation: f64,
+        npoints: u32,
+        altitude: f64,
+    },
+    /// difference between two lines from the guide star, through points
+    /// `separation` apart about the subaperture centre, see
+    /// [`Measurement::SlopeTwoLine`]
+    TwoLine { separation: f64 },
+}
+
+impl SlopeEstimator {
+    /// two-edge estimator over the whole of a square subaperture
+    fn two_edge(width: f64) -> Self {
+        Self::TwoEdge {
+            edge_length: width,
+            edge_separation: width,
+            npoints: 2,
+            altitude: f64::INFINITY,
+        }
+    }
+}
+
+/// Orientation of a sensor with respect to the pupil. A position in the
+/// frame of the sensor is flipped, divided by the magnification, shifted by
+/// `centre` and then rotated by `rotation` about the optical axis, giving the
+/// position in the pupil that is seen there.
+struct Frame {
+    centre: Vec2D,
+    /// rotation (in radians)
+    rotation: f64,
+    /// whether the sensor x (and y) axes are mirrored
+    flip: (bool, bool),
+    /// magnification of the pupil image along the sensor x and y axes
+    magnification: (f64, f64),
+}
+
+impl Frame {
+    fn new(
+        centre: (f64, f64),
+        rotation: f64,
+        flip: (bool, bool),
+        magnification: (f64, f64),
+    ) -> Self {
+        Self {
+            centre: Vec2D::new(centre.0, centre.1),
+            rotation,
+            flip,
+            magnification,
+        }
+    }
+
+    /// frame rotated by `rotation` (radians) about the optical axis
+    fn rotated(rotation: f64) -> Self {
+        Self::new((0.0, 0.0), rotation, (false, false), (1.0, 1.0))
+    }
+
+    /// Position in the pupil seen at a position in the sensor frame.
+    fn to_pupil(&self, p: &Vec2D) -> Vec2D {
+        let sign = |flip: bool| if flip { -1.0 } else { 1.0 };
+        let scaled = Vec2D::new(
+            p.x * sign(self.flip.0) / self.magnification.0,
+            p.y * sign(self.flip.1) / self.magnification.1,
+        );
+        let (sin, cos) = self.rotation.sin_cos();
+        let c = scaled + &self.centre;
+        Vec2D::new(c.x * cos - c.y * sin, c.x * sin + c.y * cos)
+    }
+
+    /// Unit vectors along the sensor x and y axes, in the pupil.
+    fn axes(&self) -> (Vec2D, Vec2D) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let x = if self.flip.0 { -1.0 } else { 1.0 };
+        let y = if self.flip.1 { -1.0 } else { 1.0 };
+        (Vec2D::new(x * cos, x * sin), Vec2D::new(-y * sin, y * cos))
+    }
+}
+
+/// Response of a pyramid WFS to the local slope at a pupil sample.
+enum PyramidRegime {
+    /// `2/pi * asin(slope / modulation)`, saturating at `+/-1` beyond the
+    /// modulation radius
+    Geometric,
+    /// `2/pi * slope / modulation`, the small-slope limit of the geometric
+    /// response
+    Linear,
+}
+
+/// Telescope pupil, defined in the pupil plane (zero altitude).
+struct Pupil {
+    geometry: rao::Pupil,
+    /// optional transmission mask, covering the full diameter
+    mask: Option<PhaseMap>,
+    /// keep masked samples in the measurement vector (as NaN) instead of
+    /// dropping them
+    flag_masked: bool,
 }
 pub struct Output {
     id: String,
     sensors: Vec<Arc<Sensor>>,
-    disturbances: Vec<Arc<Disturbance>>,
+    /// disturbances seen by each of the `sensors`
+    disturbances: Vec<Vec<Arc<Disturbance>>>,
     metric: Metric,
+    /// number of sample lines outside the support of each disturbance, which
+    /// doesn't change between evaluations
+    outside_support: BTreeMap<String, usize>,
 }
 
 enum Metric {
     WavefrontError,
     MeasurementVector,
+    /// Least-squares zernike coefficients of the phase sampled by each imager,
+    /// in the same basis as zernike disturbances.
+    ZernikeProjection {
+        /// Noll index of each coefficient
+        noll: Vec<u32>,
+        /// precomputed reconstructor of each imager (by id), from its valid
+        /// phase samples to the coefficients
+        reconstructors: BTreeMap<String, Array2<f64>>,
+    },
+    /// Like `WavefrontError`, after removing zernike modes (by least squares)
+    /// from the valid measurements of each sensor.
+    ResidualWavefrontError {
+        /// precomputed fit of each sensor (by id)
+        fits: BTreeMap<String, ModeFit>,
+    },
+    /// Image quality of the PSF of each imager.
+    Image {
+        imaging: Imaging,
+        quantity: ImageQuantity,
+        /// if present, the PSF of each imager is written to
+        /// `{psf_prefix}{imager id}.npy`
+        psf_prefix: Option<String>,
+    },
+    /// Strehl ratio of each imager in the Marechal approximation, i.e.,
+    /// `exp(-sigma^2)` for the piston-removed phase RMS `sigma` (in radians
+    /// at the imaging wavelength).
+    MarechalStrehl {
+        /// phase (in radians at the imaging wavelength) per unit of
+        /// disturbance
+        radians_per_unit: f64,
+    },
+    /// `WavefrontError`, along with a budget of the wavefront error due to
+    /// each disturbance alone.
+    ErrorBudget {
+        /// also budget the cross term of each pair of disturbances
+        cross_terms: bool,
+    },
 }
 
-impl Disturbance {
-    fn new_zernike(id: String, coeffs: Vec<f64>, radius: f64, altitude: f64) -> Self {
-        // for n radial orders, there are:
-        // l = n(n+1)/2 modes
-        // so for l modes, you need at least
-        // n = ((-1 + (1+8l).sqrt())/2).ceil
-        let minimum_radial_orders: usize =
-            ((-1.0 + (1.0 + 8.0 * coeffs.len() as f64)).sqrt() / 0.5).ceil() as usize;
-        let mut jnm: Vec<(u32, u32, u32)> = vec![];
-        let jnm_old = zernike::jnm(minimum_radial_orders as u32);
-        for i in 0..(((minimum_radial_orders + 1) * minimum_radial_orders) / 2) {
-            jnm.push((jnm_old.0[i], jnm_old.1[i], jnm_old.2[i]));
+/// Fourier optics imaging of the phase sampled by an imager, with unit
+/// amplitude at its valid samples.
+struct Imaging {
+    /// imaging wavelength (in metres)
+    wavelength: f64,
+    /// phase (in radians at `wavelength`) per unit of disturbance
+    radians_per_unit: f64,
+    /// zero padding factor of the pupil field
+    oversampling: usize,
+}
+
+impl Imaging {
+    fn psf(&self, sensor: &Sensor, disturbances: &[Arc<Disturbance>]) -> Psf {
+        let Sensor::Imager {
+            measurements,
+            valid,
+            nsamples,
+            pitch,
+            indices,
+            ..
+        } = sensor
+        else {
+            unreachable!("only imagers are imaged, see new_image");
+        };
+        let n = *nsamples;
+        let mut amplitude = Array2::<f64>::zeros((n, n));
+        let mut phase = Array2::<f64>::zeros((n, n));
+        let samples = sample_total(measurements, disturbances);
+        for ((index, sample), _) in indices.iter().zip(samples).zip(valid).filter(|(_, v)| **v) {
+            amplitude[[index / n, index % n]] = 1.0;
+            phase[[index / n, index % n]] = sample * self.radians_per_unit;
         }
+        Psf::new(
+            &amplitude,
+            &phase,
+            *pitch,
+            self.wavelength,
+            self.oversampling,
+        )
+    }
+}
+
+/// Scalar measure of image quality.
+enum ImageQuantity {
+    /// peak relative to the diffraction limited peak
+    Strehl,
+    /// full width at half maximum (in arcsec)
+    Fwhm,
+    /// fraction of the energy within a radius (in arcsec) of the peak
+    EncircledEnergy(f64),
+}
+
+/// Least-squares fit of modes to the valid measurements of a sensor.
+struct ModeFit {
+    /// response of the valid measurements to each mode, one column per mode
+    responses: Array2<f64>,
+    /// from the valid measurements to the mode coefficients
+    reconstructor: Array2<f64>,
+}
+
+impl ModeFit {
+    /// measurements minus their best fit by the modes
+    fn residual(&self, values: Array1<f64>) -> Array1<f64> {
+        let fit = self.responses.dot(&self.reconstructor.dot(&values));
+        values - fit
+    }
+}
+
+impl Disturbance {
+    /// Each coefficient is paired with the `(n, m)` of its mode, see
+    /// [`zernike_index`] for the sign convention of `m`. A non-zero
+    /// `obscuration` ratio switches to the annular zernike basis.
+    fn new_zernike(
+        id: String,
+        coeffs: Vec<NmCoefficient>,
+        radius: f64,
+        altitude: f64,
+        obscuration: f64,
+        outside: Outside,
+    ) -> Self {
+        let (jnm, coeffs): (Vec<(u32, u32, u32)>, Vec<f64>) = coeffs
+            .into_iter()
+            .map(|((n, m), value)| {
+                let j = zernike_index::Convention::Noll
+                    .nm_to_index(n, m)
+                    .expect("(n, m) validated by config");
+                ((j, n, m.unsigned_abs()), value)
+            })
+            .unzip();
+        let annular = (obscuration > 0.0).then(|| {
+            jnm.iter()
+                .map(|&(_, n, m)| annular::radial_polynomial(n, m, obscuration))
+                .collect()
+        });
         Disturbance::Zernike {
             id,
             coeffs,
             radius,
             altitude,
             jnm,
+            annular,
+            obscuration,
+            outside,
+        }
+    }
+
+    fn new_von_karman(id: String, screen: PhaseScreen, altitude: f64, shift: (f64, f64)) -> Self {
+        Disturbance::VonKarman {
+            id,
+            screen,
+            altitude,
+            shift: Vec2D::new(shift.0, shift.1),
+        }
+    }
+
+    fn new_dm(id: String, grid: ActuatorGrid, altitude: f64) -> Self {
+        Disturbance::Dm { id, grid, altitude }
+    }
+
+    fn new_phase_map(id: String, map: PhaseMap, altitude: f64) -> Self {
+        Disturbance::PhaseMap { id, map, altitude }
+    }
+
+    fn new_scaled(disturbance: Arc<Disturbance>, gain: f64) -> Self {
+        Disturbance::Scaled { gain, disturbance }
+    }
+
+    fn id(&self) -> &String {
+        match self {
+            Self::Zernike { id, .. } => id,
+            Self::VonKarman { id, .. } => id,
+            Self::Dm { id, .. } => id,
+            Self::PhaseMap { id, .. } => id,
+            Self::Scaled { disturbance, .. } => disturbance.id(),
+        }
+    }
+
+    /// Whether a line intercepts this disturbance outside of the region where
+    /// it is defined (e.g., beyond the unit disk of a zernike basis, or off
+    /// the edge of a phase screen).
+    fn outside_support(&self, p: &Line) -> bool {
+        match self {
+            Self::Zernike {
+                radius,
+                altitude,
+                obscuration,
+                ..
+            } => {
+                let r = p.position_at_altitude(*altitude).norm() / radius;
+                r > 1.0 || r < *obscuration
+            }
+            Self::VonKarman {
+                screen,
+                altitude,
+                shift,
+                ..
+            } => !screen.contains(&(p.position_at_altitude(*altitude) - shift)),
+            Self::Dm { grid, altitude, .. } => !grid.contains(&p.position_at_altitude(*altitude)),
+            Self::PhaseMap { map, altitude, .. } => {
+                !map.contains(&p.position_at_altitude(*altitude))
+            }
+            Self::Scaled { disturbance, .. } => disturbance.outside_support(p),
+        }
+    }
+
+    /// Whether sampling outside of the support is an error
+    fn errors_outside(&self) -> bool {
+        match self {
+            Self::Zernike { outside, .. } => matches!(outside, Outside::Error),
+            Self::Scaled { disturbance, .. } => disturbance.errors_outside(),
+            Self::VonKarman { .. } | Self::Dm { .. } | Self::PhaseMap { .. } => false,
         }
     }
 }
@@ -88,153 +550,629 @@ impl Sampleable for Disturbance {
                 jnm,
                 radius,
                 altitude,
+                annular,
+                obscuration,
+                outside,
                 ..
             } => {
                 let pos = p.position_at_altitude(*altitude);
-                let r = pos.norm() / radius;
+                let mut r = pos.norm() / radius;
                 let theta = pos.y.atan2(pos.x);
-                (0..coeffs.len()).into_iter()
-                    .map(|i| coeffs[i] * zernike::zernike(jnm[i].0, jnm[i].1, jnm[i].2, r, theta))
-                    .sum()
+                if r > 1.0 || r < *obscuration {
+                    match outside {
+                        Outside::Extrapolate => (),
+                        Outside::Zero => return 0.0,
+                        Outside::Error => return f64::NAN,
+                        Outside::Clamp => r = r.clamp(*obscuration, 1.0),
+                    }
+                }
+                match annular {
+                    None => (0..coeffs.len())
+                        .map(|i| {
+                            coeffs[i] * circular_zernike(jnm[i].0, jnm[i].1, jnm[i].2, r, theta)
+                        })
+                        .sum(),
+                    Some(radials) => (0..coeffs.len())
+                        .map(|i| {
+                            let (j, _, m) = jnm[i];
+                            // even j are cosine modes, odd j are sine modes
+                            let azimuthal = match (m, j % 2) {
+                                (0, _) => 1.0,
+                                (_, 0) => f64::consts::SQRT_2 * (m as f64 * theta).cos(),
+                                _ => f64::consts::SQRT_2 * (m as f64 * theta).sin(),
+                            };
+                            coeffs[i] * annular::evaluate(&radials[i], r) * azimuthal
+                        })
+                        .sum(),
+                }
             }
+            Self::VonKarman {
+                screen,
+                altitude,
+                shift,
+                ..
+            } => screen.interpolate(&(p.position_at_altitude(*altitude) - shift)),
+            Self::Dm { grid, altitude, .. } => grid.surface(&p.position_at_altitude(*altitude)),
+            Self::PhaseMap { map, altitude, .. } => {
+                map.interpolate(&p.position_at_altitude(*altitude))
+            }
+            Self::Scaled { gain, disturbance } => gain * disturbance.sample(p),
+        }
+    }
+}
+
+impl Pupil {
+    fn new(
+        diameter: f64,
+        obscuration: f64,
+        spider_width: f64,
+        // spider angles in radians, each spider runs from the centre outwards
+        spider_angles: Vec<f64>,
+        mask: Option<PhaseMap>,
+        flag_masked: bool,
+    ) -> Self {
+        let rad_outer = diameter / 2.0;
+        let spiders = spider_angles
+            .into_iter()
+            .map(|angle| {
+                (
+                    Vec2D::new(0.0, 0.0),
+                    Vec2D::new(rad_outer * angle.cos(), rad_outer * angle.sin()),
+                )
+            })
+            .collect();
+        Self {
+            geometry: rao::Pupil {
+                rad_outer,
+                rad_inner: rad_outer * obscuration,
+                spider_thickness: spider_width,
+                spiders,
+            },
+            mask,
+            flag_masked,
         }
     }
+
+    /// Whether light passes through the pupil at a position (in metres).
+    fn transmits(&self, pos: &Vec2D) -> bool {
+        let masked = match &self.mask {
+            Some(mask) => mask.interpolate(pos) <= 0.5,
+            None => false,
+        };
+        self.geometry.sample(&Line::new_on_axis(pos.x, pos.y)) > 0.5 && !masked
+    }
+
+    /// Fraction of a rectangular subaperture (of width and height `size`,
+    /// centred on `centre` and rotated by `rotation` radians) through which
+    /// light passes.
+    fn illumination(&self, centre: &Vec2D, size: (f64, f64), rotation: f64) -> f64 {
+        // samples across each side of the subaperture
+        const NSAMPLES: usize = 16;
+        let offsets: Vec<Vec2D> = square_grid(NSAMPLES, 1.0 / NSAMPLES as f64, (0.0, 0.0), 0.0)
+            .into_iter()
+            .map(|p| Vec2D::new(p.x * size.0, p.y * size.1))
+            .collect();
+        let count = place(offsets, (0.0, 0.0), rotation)
+            .into_iter()
+            .filter(|offset| self.transmits(&(centre + offset)))
+            .count();
+        count as f64 / (NSAMPLES * NSAMPLES) as f64
+    }
 }
 
 impl Sensor {
+    fn id(&self) -> &String {
+        match self {
+            Sensor::Shwfs { id, .. } => id,
+            Sensor::Imager { id, .. } => id,
+            Sensor::Pyramid { id, .. } => id,
+            Sensor::ZernikeProjection { id, .. } => id,
+            Sensor::Curvature { id, .. } => id,
+            Sensor::LowOrder { id, .. } => id,
+        }
+    }
+
+    /// Measurements that aren't flagged as masked by the pupil.
+    fn valid_measurements(&self) -> Vec<&Measurement> {
+        match self {
+            Sensor::Shwfs {
+                measurements,
+                valid,
+                ..
+            }
+            | Sensor::Imager {
+                measurements,
+                valid,
+                ..
+            }
+            | Sensor::Pyramid {
+                measurements,
+                valid,
+                ..
+            } => measurements
+                .iter()
+                .zip(valid)
+                .filter_map(|(measurement, valid)| valid.then_some(measurement))
+                .collect(),
+            Sensor::ZernikeProjection { measurements, .. }
+            | Sensor::Curvature { measurements, .. }
+            | Sensor::LowOrder { measurements, .. } => measurements.iter().collect(),
+        }
+    }
+
+    /// Convert a sampled measurement into the value reported by the sensor.
+    fn signal(&self, value: f64) -> f64 {
+        match self {
+            Sensor::Shwfs { .. }
+            | Sensor::Imager { .. }
+            | Sensor::ZernikeProjection { .. }
+            | Sensor::Curvature { .. }
+            | Sensor::LowOrder { .. } => value,
+            Sensor::Pyramid {
+                modulation,
+                gain,
+                regime,
+                ..
+            } => {
+                let ratio = value / modulation;
+                let response = match regime {
+                    PyramidRegime::Geometric => ratio.clamp(-1.0, 1.0).asin(),
+                    PyramidRegime::Linear => ratio,
+                };
+                gain * f64::consts::FRAC_2_PI * response
+            }
+        }
+    }
+
+    /// Add the noise realisation of the sensor (if any) to its values.
+    fn add_noise(&self, mut values: Vec<f64>) -> Vec<f64> {
+        match self {
+            Sensor::Shwfs {
+                noise: Some(noise),
+                sigma,
+                ..
+            }
+            | Sensor::LowOrder {
+                noise: Some(noise),
+                sigma,
+                ..
+            } => noise.add_to(&mut values, sigma),
+            _ => (),
+        }
+        values
+    }
+
+    /// Drop (or flag as invalid) measurements that the pupil doesn't
+    /// illuminate. For an imager, that is any sample whose principal line
+    /// crosses the pupil plane where the pupil is opaque. For a SH-WFS, it
+    /// is both slopes of any subaperture less than `min_illumination` lit.
+    fn apply_pupil(&mut self, pupil: &Pupil) {
+        let transmits: Vec<bool> = match self {
+            Sensor::Shwfs {
+                lenslets,
+                subwidth,
+                frame,
+                min_illumination,
+                subapertures,
+                noise,
+                sigma,
+                ..
+            } => {
+                let size = (
+                    *subwidth / frame.magnification.0,
+                    *subwidth / frame.magnification.1,
+                );
+                let illumination: Vec<f64> = lenslets
+                    .iter()
+                    .map(|c| pupil.illumination(c, size, frame.rotation))
+                    .collect();
+                *subapertures = illumination
+                    .iter()
+                    .map(|fraction| *fraction >= *min_illumination)
+                    .collect();
+                // the x slope of every subaperture, then all of the y slopes
+                if let Some(noise) = noise {
+                    let lenslet_sigma: Vec<f64> = illumination
+                        .iter()
+                        .map(|fraction| noise.slope_sigma(*fraction))
+                        .collect();
+                    *sigma = [lenslet_sigma.clone(), lenslet_sigma].concat();
+                }
+                [subapertures.clone(), subapertures.clone()].concat()
+            }
+            Sensor::Imager { measurements, .. } | Sensor::Pyramid { measurements, .. } => {
+                measurements
+                    .iter()
+                    .map(|measurement| pupil.transmits(&pupil_position(measurement)))
+                    .collect()
+            }
+            // the pupil is accounted for when the sensor is built
+            Sensor::ZernikeProjection { .. }
+            | Sensor::Curvature { .. }
+            | Sensor::LowOrder { .. } => return,
+        };
+        let (measurements, valid) = match self {
+            Sensor::Shwfs {
+                measurements,
+                valid,
+                ..
+            } => (measurements, valid),
+            Sensor::Imager {
+                measurements,
+                valid,
+                ..
+            } => (measurements, valid),
+            Sensor::Pyramid {
+                measurements,
+                valid,
+                ..
+            } => (measurements, valid),
+            Sensor::ZernikeProjection { .. }
+            | Sensor::Curvature { .. }
+            | Sensor::LowOrder { .. } => unreachable!(),
+        };
+        if pupil.flag_masked {
+            valid.iter_mut().zip(&transmits).for_each(|(v, t)| *v &= t);
+        } else {
+            let mut keep = transmits.iter();
+            measurements.retain(|_| *keep.next().unwrap());
+            let mut keep = transmits.iter();
+            valid.retain(|_| *keep.next().unwrap());
+            if let Sensor::Shwfs { sigma, .. } = self {
+                let mut keep = transmits.iter();
+                sigma.retain(|_| *keep.next().unwrap());
+            }
+            if let Sensor::Imager { indices, .. } = self {
+                let mut keep = transmits.iter();
+                indices.retain(|_| *keep.next().unwrap());
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn new_shwfs(
         // id used for display purposes only here
         id: &str,
-        // number of subapertures in each dimensions of MLA
-        nsubx: usize,
-        // subaperture width in metres (wrt pupil)
+        // lenslet centres in metres, in the frame of the MLA
+        layout: Vec<Vec2D>,
+        // subaperture width in metres (wrt pupil, at unit magnification)
         subwidth: f64,
         // centre of MLA in metres (wrt pupil)
         centre: (f64, f64),
         // rotation of MLA wrt pupil in degrees
         rotation: f64,
+        // whether the MLA x and y axes are mirrored wrt pupil
+        flip: (bool, bool),
+        // magnification of the pupil image along the MLA x and y axes
+        magnification: (f64, f64),
         // direction of GS in arcsec
         direction: (f64, f64),
         // guide star altitude in metres
         gsalt: f64,
+        // minimum illuminated fraction of a valid subaperture
+        min_illumination: f64,
+        estimator: SlopeEstimator,
+        noise: Option<NoiseModel>,
     ) -> Self {
-        let x = rao::Vec2D::linspread(
-            &Vec2D::new(-subwidth * (nsubx as f64) / 2.0, 0.0),
-            &Vec2D::new(subwidth * (nsubx as f64) / 2.0, 0.0),
-            nsubx as u32,
-        );
-        let y = rao::Vec2D::linspread(
-            &Vec2D::new(0.0, -subwidth * (nsubx as f64) / 2.0),
-            &Vec2D::new(0.0, subwidth * (nsubx as f64) / 2.0),
-            nsubx as u32,
-        );
-        let centre = Vec2D::new(centre.0, centre.1);
-        let mut centres = y
-            .into_iter()
-            .flat_map(move |y| {
-                let c = centre.clone();
-                x.clone().into_iter().map(move |x| x + &y + &c)
-            })
-            .collect::<Vec<Vec2D>>();
-        // rotate the centres
         let rotation_rad = rotation * f64::consts::PI / 180.0;
-        centres = centres
-            .into_iter()
-            .map(|c| {
-                Vec2D::new(
-                    c.x * rotation_rad.cos() - c.y * rotation_rad.sin(),
-                    c.x * rotation_rad.sin() + c.y * rotation_rad.cos(),
-                )
-            })
-            .collect();
-        let axis = Line::new(0.0, direction.0 * AS2RAD, 0.0, direction.1 * AS2RAD);
-        let gspos3d = Vec3D::new(
-            axis.position_at_altitude(gsalt).x,
-            axis.position_at_altitude(gsalt).y,
-            gsalt,
+        let frame = Frame::new(centre, rotation_rad, flip, magnification);
+        let centres: Vec<Vec2D> = layout.iter().map(|p| frame.to_pupil(p)).collect();
+        let gspos3d = guide_star(direction, gsalt);
+        let slopes = slopes(&centres, &estimator, &frame, &gspos3d);
+        let sigma = noise.as_ref().map_or(0.0, |noise| noise.slope_sigma(1.0));
+        Self::Shwfs {
+            id: id.to_string(),
+            valid: vec![true; slopes.len()],
+            sigma: vec![sigma; slopes.len()],
+            noise,
+            measurements: slopes,
+            subapertures: vec![true; centres.len()],
+            lenslets: centres,
+            subwidth,
+            frame,
+            min_illumination,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_pyramid(
+        // id used for display purposes only here
+        id: &str,
+        // number of pupil samples in each dimension
+        nsamples: usize,
+        // distance between adjacent pupil samples in metres (wrt pupil)
+        pitch: f64,
+        // centre of the sampled pupil in metres (wrt pupil)
+        centre: (f64, f64),
+        // rotation of the pyramid wrt pupil in degrees
+        rotation: f64,
+        // direction of GS in arcsec
+        direction: (f64, f64),
+        // guide star altitude in metres
+        gsalt: f64,
+        // modulation radius, in the same units as the slopes
+        modulation: f64,
+        // optical gain applied to the signals
+        gain: f64,
+        regime: PyramidRegime,
+    ) -> Self {
+        let rotation_rad = rotation * f64::consts::PI / 180.0;
+        let centres = square_grid(nsamples, pitch, centre, rotation_rad);
+        let gspos3d = guide_star(direction, gsalt);
+        let slopes = slopes(
+            &centres,
+            &SlopeEstimator::two_edge(pitch),
+            &Frame::rotated(rotation_rad),
+            &gspos3d,
         );
-        let x_slopes: Vec<Measurement> = centres
+        Self::Pyramid {
+            id: id.to_string(),
+            valid: vec![true; slopes.len()],
+            measurements: slopes,
+            modulation,
+            gain,
+            regime,
+        }
+    }
+
+    /// Fits modes `1..=nmodes` (Noll) to phase samples on a square grid,
+    /// keeping only the samples within `radius` (and through the pupil, if
+    /// any). `None` if there are too few samples to fit that many modes.
+    fn new_zernike_projection(
+        // id used for display purposes only here
+        id: &str,
+        // number of Noll modes to fit, starting from piston
+        nmodes: u32,
+        // radius of the zernike basis in metres (wrt pupil)
+        radius: f64,
+        // number of samples across the diameter of the basis
+        nsamples: usize,
+        // direction of GS in arcsec
+        direction: (f64, f64),
+        // guide star altitude in metres
+        gsalt: f64,
+        pupil: Option<&Pupil>,
+    ) -> Option<Self> {
+        let positions: Vec<Vec2D> =
+            square_grid(nsamples, 2.0 * radius / nsamples as f64, (0.0, 0.0), 0.0)
+                .into_iter()
+                .filter(|pos| pos.norm() <= radius)
+                .filter(|pos| pupil.is_none_or(|pupil| pupil.transmits(pos)))
+                .collect();
+        let noll: Vec<u32> = (1..=nmodes).collect();
+        let reconstructor = linalg::least_squares(&zernike_basis(&positions, radius, &noll))?;
+        let gspos3d = guide_star(direction, gsalt);
+        let measurements = positions
             .iter()
-            .map(|c| Measurement::SlopeTwoEdge {
-                central_line: Line::new_from_two_points(&Vec3D::new(c.x, c.y, 0.0), &gspos3d),
-                edge_length: subwidth,
-                edge_separation: subwidth,
-                gradient_axis: Vec2D::new(rotation_rad.cos(), rotation_rad.sin()),
-                npoints: 2,
-                altitude: f64::INFINITY,
+            .map(|c| Measurement::Phase {
+                line: Line::new_from_two_points(&Vec3D::new(c.x, c.y, 0.0), &gspos3d),
             })
             .collect();
-        let y_slopes: Vec<Measurement> = centres
+        Some(Self::ZernikeProjection {
+            id: id.to_string(),
+            measurements,
+            noll,
+            reconstructor,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_curvature(
+        // id used for display purposes only here
+        id: &str,
+        // subaperture centres in metres, before shifting and rotating
+        layout: Vec<Vec2D>,
+        // subaperture width in metres (wrt pupil)
+        subwidth: f64,
+        // centre of the subaperture grid in metres (wrt pupil)
+        centre: (f64, f64),
+        // rotation of the subaperture grid wrt pupil in degrees
+        rotation: f64,
+        // direction of GS in arcsec
+        direction: (f64, f64),
+        // guide star altitude in metres
+        gsalt: f64,
+        // equivalent propagation distance in the pupil space, in metres
+        distance: f64,
+        // optical path difference in metres per unit of phase
+        metres_per_unit: f64,
+        // number of segments along each side of a subaperture
+        npoints: usize,
+        // minimum illuminated fraction of a valid subaperture
+        min_illumination: f64,
+        pupil: Option<&Pupil>,
+    ) -> Self {
+        let rotation_rad = rotation * f64::consts::PI / 180.0;
+        let centres = place(layout, centre, rotation_rad);
+        let gspos3d = guide_star(direction, gsalt);
+        let line_through =
+            |p: &Vec2D| Line::new_from_two_points(&Vec3D::new(p.x, p.y, 0.0), &gspos3d);
+        let segment = subwidth / npoints as f64;
+        let mut measurements = vec![];
+        let mut weights = vec![];
+        let mut subapertures = vec![];
+        let mut nsignals = 0;
+        for c in &centres {
+            let illumination = match pupil {
+                Some(pupil) => pupil.illumination(c, (subwidth, subwidth), rotation_rad),
+                None => 1.0,
+            };
+            let valid = illumination > 0.0 && illumination >= min_illumination;
+            subapertures.push(valid);
+            if !valid {
+                continue;
+            }
+            let index = nsignals;
+            nsignals += 1;
+            let area = illumination * subwidth.powi(2);
+            for side in 0..4 {
+                let angle = rotation_rad + side as f64 * f64::consts::FRAC_PI_2;
+                let normal = Vec2D::new(angle.cos(), angle.sin());
+                let along = normal.ortho();
+                for k in 0..npoints {
+                    let offset = ((k as f64 + 0.5) / npoints as f64 - 0.5) * subwidth;
+                    let midpoint = c + &normal * (subwidth / 2.0) + &along * offset;
+                    if pupil.is_some_and(|pupil| !pupil.transmits(&midpoint)) {
+                        continue;
+                    }
+                    measurements.push(Measurement::SlopeTwoEdge {
+                        central_line: line_through(&midpoint),
+                        edge_length: segment,
+                        edge_separation: segment,
+                        gradient_axis: normal.clone(),
+                        npoints: 1,
+                        altitude: f64::INFINITY,
+                    });
+                    weights.push((index, -distance * metres_per_unit * segment / area));
+                }
+            }
+        }
+        Self::Curvature {
+            id: id.to_string(),
+            measurements,
+            nsignals,
+            weights,
+            subapertures,
+        }
+    }
+
+    /// Averages the gradient over a `density` by `density` grid of cells in
+    /// each subaperture, keeping the cells through the pupil (if any).
+    /// Fails if doubling the density moves the illuminated fraction or
+    /// centroid of any subaperture by more than `tolerance` (as a fraction
+    /// of the subaperture width), i.e., if the sampling hasn't converged.
+    #[allow(clippy::too_many_arguments)]
+    fn new_low_order(
+        // id used for display purposes only here
+        id: &str,
+        // number of subapertures in each dimension, 1 for a tip-tilt sensor
+        nsubx: usize,
+        // subaperture width in metres (wrt pupil)
+        subwidth: f64,
+        // centre of MLA in metres (wrt pupil)
+        centre: (f64, f64),
+        // rotation of MLA wrt pupil in degrees
+        rotation: f64,
+        // direction of GS in arcsec
+        direction: (f64, f64),
+        // guide star altitude in metres
+        gsalt: f64,
+        // number of cells across each subaperture
+        density: usize,
+        // minimum illuminated fraction of a valid subaperture
+        min_illumination: f64,
+        // maximum difference of illumination from a much finer sampling
+        tolerance: f64,
+        noise: Option<NoiseModel>,
+        pupil: Option<&Pupil>,
+    ) -> Result<Self, String> {
+        let rotation_rad = rotation * f64::consts::PI / 180.0;
+        let centres = square_grid(nsubx, subwidth, centre, rotation_rad);
+        let gspos3d = guide_star(direction, gsalt);
+        // cells of a subaperture through the pupil, and their number
+        let lit_cells = |c: &Vec2D, density: usize| -> (Vec<Vec2D>, usize) {
+            let cells: Vec<Vec2D> =
+                square_grid(density, subwidth / density as f64, (0.0, 0.0), rotation_rad)
+                    .into_iter()
+                    .map(|cell| c + cell)
+                    .filter(|cell| pupil.is_none_or(|pupil| pupil.transmits(cell)))
+                    .collect();
+            (cells, density * density)
+        };
+        let mut subapertures = vec![];
+        let mut cells = vec![];
+        let mut fractions = vec![];
+        for (i, c) in centres.iter().enumerate() {
+            let (lit, total) = lit_cells(c, density);
+            let (fine, fine_total) = lit_cells(c, REFERENCE_DENSITY_FACTOR * density);
+            let fraction = lit.len() as f64 / total as f64;
+            let fine_fraction = fine.len() as f64 / fine_total as f64;
+            let centroid = |cells: &[Vec2D]| {
+                let n = cells.len().max(1) as f64;
+                let sum = cells.iter().fold(Vec2D::new(0.0, 0.0), |acc, c| acc + c);
+                sum / n
+            };
+            let change = (fraction - fine_fraction)
+                .abs()
+                .max((centroid(&lit) - centroid(&fine)).norm() / subwidth);
+            if change > tolerance {
+                return Err(format!(
+                    "sampling of subaperture {i} hasn't converged (differs by {change:.3} \
+                    from {REFERENCE_DENSITY_FACTOR} times the density), increase the density"
+                ));
+            }
+            let valid = fraction > 0.0 && fraction >= min_illumination;
+            subapertures.push(valid);
+            if valid {
+                cells.push(lit);
+                fractions.push(fraction);
+            }
+        }
+        let cell_width = subwidth / density as f64;
+        let mut measurements = vec![];
+        let mut weights = vec![];
+        for (axis, angle) in [rotation_rad, rotation_rad + f64::consts::FRAC_PI_2]
+            .into_iter()
+            .enumerate()
+        {
+            for (i, lit) in cells.iter().enumerate() {
+                let estimator = SlopeEstimator::two_edge(cell_width);
+                let slopes = slopes(lit, &estimator, &Frame::rotated(angle), &gspos3d);
+                // only the first half are along this axis
+                measurements.extend(slopes.into_iter().take(lit.len()));
+                let weight = 1.0 / lit.len() as f64;
+                weights.extend(vec![(axis * cells.len() + i, weight); lit.len()]);
+            }
+        }
+        let sigma: Vec<f64> = fractions
             .iter()
-            .map(|c| Measurement::SlopeTwoEdge {
-                central_line: Line::new_from_two_points(&Vec3D::new(c.x, c.y, 0.0), &gspos3d),
-                edge_length: subwidth,
-                edge_separation: subwidth,
-                gradient_axis: Vec2D::new(
-                    (rotation_rad + f64::consts::FRAC_PI_2).cos(),
-                    (rotation_rad + f64::consts::FRAC_PI_2).sin(),
-                ),
-                npoints: 2,
-                altitude: f64::INFINITY,
+            .map(|fraction| {
+                noise
+                    .as_ref()
+                    .map_or(0.0, |noise| noise.slope_sigma(*fraction))
             })
             .collect();
-        let slopes: Vec<Measurement> = [x_slopes, y_slopes].concat();
-        Self::Shwfs {
+        Ok(Self::LowOrder {
             id: id.to_string(),
-            measurements: slopes,
-        }
+            measurements,
+            weights,
+            nsignals: 2 * cells.len(),
+            subapertures,
+            noise,
+            // the x slope of every subaperture, then all of the y slopes
+            sigma: [sigma.clone(), sigma].concat(),
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new_imager(
         // id used for display purposes only here
         id: &str,
         // number of samples in each dimensions of pupil
         nsample: usize,
-        // distance between adjacent phase points in metres (wrt pupil)
+        // distance between adjacent phase points in metres (wrt pupil, at
+        // unit magnification)
         pitch: f64,
         // centre of MLA in metres (wrt pupil)
         centre: (f64, f64),
         // rotation of MLA wrt pupil in degrees
         rotation: f64,
+        // whether the imager x and y axes are mirrored wrt pupil
+        flip: (bool, bool),
+        // magnification of the pupil image along the imager x and y axes
+        magnification: (f64, f64),
         // direction of GS in arcsec
         direction: (f64, f64),
         // guide star altitude in metres
         gsalt: f64,
     ) -> Self {
-        let x = rao::Vec2D::linspread(
-            &Vec2D::new(-pitch * (nsample as f64) / 2.0, 0.0),
-            &Vec2D::new(pitch * (nsample as f64) / 2.0, 0.0),
-            nsample as u32,
-        );
-        let y = rao::Vec2D::linspread(
-            &Vec2D::new(-pitch * (nsample as f64) / 2.0, 0.0),
-            &Vec2D::new(pitch * (nsample as f64) / 2.0, 0.0),
-            nsample as u32,
-        );
-        let centre = Vec2D::new(centre.0, centre.1);
-        let mut centres = y
-            .into_iter()
-            .flat_map(move |y| {
-                let c = centre.clone();
-                x.clone().into_iter().map(move |x| x + &y + &c)
-            })
-            .collect::<Vec<Vec2D>>();
         let rotation_rad = rotation * f64::consts::PI / 180.0;
-        centres = centres
-            .into_iter()
-            .map(|c| {
-                Vec2D::new(
-                    c.x * rotation_rad.cos() - c.y * rotation_rad.sin(),
-                    c.x * rotation_rad.sin() + c.y * rotation_rad.cos(),
-                )
-            })
+        let frame = Frame::new(centre, rotation_rad, flip, magnification);
+        let centres: Vec<Vec2D> = square_grid(nsample, pitch, (0.0, 0.0), 0.0)
+            .iter()
+            .map(|p| frame.to_pupil(p))
             .collect();
-        let axis = Line::new(0.0, direction.0 * AS2RAD, 0.0, direction.1 * AS2RAD);
-        let gspos3d = Vec3D::new(
-            axis.position_at_altitude(gsalt).x,
-            axis.position_at_altitude(gsalt).y,
-            gsalt,
-        );
+        let gspos3d = guide_star(direction, gsalt);
         let meas: Vec<Measurement> = centres
             .iter()
             .map(|c| Measurement::Phase {
@@ -243,85 +1181,666 @@ impl Sensor {
             .collect();
         Self::Imager {
             id: id.to_string(),
+            valid: vec![true; meas.len()],
+            indices: (0..meas.len()).collect(),
             measurements: meas,
+            nsamples: nsample,
+            pitch: (pitch / magnification.0, pitch / magnification.1),
         }
     }
 }
 
+/// Factor by which the density of the reference sampling of a low-order WFS
+/// subaperture exceeds that of the sampling being checked for convergence.
+const REFERENCE_DENSITY_FACTOR: usize = 8;
+
+/// Shift positions by `centre` and then rotate them by `rotation` (radians)
+/// about the optical axis.
+fn place(positions: Vec<Vec2D>, centre: (f64, f64), rotation: f64) -> Vec<Vec2D> {
+    let centre = Vec2D::new(centre.0, centre.1);
+    positions
+        .into_iter()
+        .map(|p| p + &centre)
+        .map(|c| {
+            Vec2D::new(
+                c.x * rotation.cos() - c.y * rotation.sin(),
+                c.x * rotation.sin() + c.y * rotation.cos(),
+            )
+        })
+        .collect()
+}
+
+/// Centres of a square `n` by `n` grid with spacing `pitch` (row-major, x
+/// fastest), shifted by `centre` and then rotated by `rotation` (radians)
+/// about the optical axis.
+fn square_grid(n: usize, pitch: f64, centre: (f64, f64), rotation: f64) -> Vec<Vec2D> {
+    let x = rao::Vec2D::linspread(
+        &Vec2D::new(-pitch * (n as f64) / 2.0, 0.0),
+        &Vec2D::new(pitch * (n as f64) / 2.0, 0.0),
+        n as u32,
+    );
+    let y = rao::Vec2D::linspread(
+        &Vec2D::new(0.0, -pitch * (n as f64) / 2.0),
+        &Vec2D::new(0.0, pitch * (n as f64) / 2.0),
+        n as u32,
+    );
+    let grid = y
+        .into_iter()
+        .flat_map(move |y| x.clone().into_iter().map(move |x| x + &y))
+        .collect();
+    place(grid, centre, rotation)
+}
+
+/// Centres of a hexagonal lattice with spacing `pitch`, with rows along x
+/// and one lattice point at the origin, keeping those within a circle `n`
+/// pitches across (row by row from -y, x fastest).
+fn hexagonal_grid(n: usize, pitch: f64) -> Vec<Vec2D> {
+    let radius = n as f64 * pitch / 2.0;
+    let row_pitch = pitch * 3f64.sqrt() / 2.0;
+    let nrows = (radius / row_pitch).floor() as i64;
+    let ncols = (radius / pitch).ceil() as i64;
+    (-nrows..=nrows)
+        .flat_map(|j| {
+            // every other row is offset by half a pitch
+            let shift = j.rem_euclid(2) as f64 / 2.0;
+            (-ncols..=ncols)
+                .map(move |i| Vec2D::new((i as f64 + shift) * pitch, j as f64 * row_pitch))
+        })
+        .filter(|p| p.norm() <= radius * (1.0 + 1e-9))
+        .collect()
+}
+
+/// Zernike modes (as evaluated for zernike disturbances) at each position,
+/// with one row per position and one column per Noll index.
+fn zernike_basis(positions: &[Vec2D], radius: f64, noll: &[u32]) -> Array2<f64> {
+    Array2::from_shape_fn((positions.len(), noll.len()), |(i, k)| {
+        let (n, m) = zernike_index::Convention::Noll
+            .index_to_nm(noll[k])
+            .expect("Noll indices start at 1");
+        let r = positions[i].norm() / radius;
+        let theta = positions[i].y.atan2(positions[i].x);
+        circular_zernike(noll[k], n, m.unsigned_abs(), r, theta)
+    })
+}
+
+/// Circular zernike mode, where `j` is the Noll index of `(n, |m|)`.
+///
+/// The radial term of the zernike crate (pinned to 0.2.1, later versions
+/// normalise it) leaves out the `sqrt(n + 1)` of Noll's normalisation, so
+/// each mode has an RMS of `1/sqrt(n + 1)` over the unit disk. This is kept
+/// so that existing coefficients give the same phase.
+fn circular_zernike(j: u32, n: u32, m: u32, r: f64, theta: f64) -> f64 {
+    zernike::zernike(j, n, m, r, theta)
+}
+
+/// Position of a guide star, given its direction (in arcsec) and altitude.
+fn guide_star(direction: (f64, f64), gsalt: f64) -> Vec3D {
+    let axis = Line::new(0.0, direction.0 * AS2RAD, 0.0, direction.1 * AS2RAD);
+    Vec3D::new(
+        axis.position_at_altitude(gsalt).x,
+        axis.position_at_altitude(gsalt).y,
+        gsalt,
+    )
+}
+
+/// Slopes at each of the `centres` (in the pupil): all of the x slopes,
+/// followed by all of the y slopes, along the x and y axes of `frame`. The
+/// estimator is defined in the sensor frame, so it is shrunk (or stretched)
+/// by the magnification of the frame along each axis, while the slopes remain
+/// gradients of the phase in the pupil.
+fn slopes(
+    centres: &[Vec2D],
+    estimator: &SlopeEstimator,
+    frame: &Frame,
+    gspos3d: &Vec3D,
+) -> Vec<Measurement> {
+    // magnification along and across the gradient axis
+    let slope = |c: &Vec2D, gradient_axis: &Vec2D, along: f64, across: f64| {
+        let gradient_axis = gradient_axis.clone();
+        match *estimator {
+            SlopeEstimator::TwoEdge {
+                edge_length,
+                edge_separation,
+                npoints,
+                altitude,
+            } => Measurement::SlopeTwoEdge {
+                central_line: Line::new_from_two_points(&Vec3D::new(c.x, c.y, 0.0), gspos3d),
+                edge_length: edge_length / across,
+                edge_separation: edge_separation / along,
+                gradient_axis,
+                npoints,
+                altitude,
+            },
+            SlopeEstimator::TwoLine { separation } => {
+                let line_through =
+                    |p: Vec2D| Line::new_from_two_points(&Vec3D::new(p.x, p.y, 0.0), gspos3d);
+                let offset = &gradient_axis * (separation / along / 2.0);
+                Measurement::SlopeTwoLine {
+                    line_pos: line_through(c + &offset),
+                    line_neg: line_through(c - &offset),
+                }
+            }
+        }
+    };
+    let (x_axis, y_axis) = frame.axes();
+    let (mx, my) = frame.magnification;
+    let x_slopes = centres.iter().map(|c| slope(c, &x_axis, mx, my));
+    let y_slopes = centres.iter().map(|c| slope(c, &y_axis, my, mx));
+    x_slopes.chain(y_slopes).collect()
+}
+
 impl Metric {
+    /// Zernike projection of the phase sampled by each of the `sensors`,
+    /// which must all be imagers.
+    fn new_zernike_projection(
+        nmodes: u32,
+        // radius of the zernike basis in metres (wrt pupil)
+        radius: f64,
+        sensors: &[Arc<Sensor>],
+    ) -> Result<Self, String> {
+        let noll: Vec<u32> = (1..=nmodes).collect();
+        let reconstructors = sensors
+            .iter()
+            .map(|sensor| match sensor.as_ref() {
+                Sensor::Imager {
+                    id,
+                    measurements,
+                    valid,
+                    ..
+                } => {
+                    let positions: Vec<Vec2D> = measurements
+                        .iter()
+                        .zip(valid)
+                        .filter(|(_, valid)| **valid)
+                        .map(|(measurement, _)| pupil_position(measurement))
+                        .collect();
+                    let reconstructor =
+                        linalg::least_squares(&zernike_basis(&positions, radius, &noll))
+                            .ok_or(format!("too few samples in {id} to fit {nmodes} modes"))?;
+                    Ok((id.clone(), reconstructor))
+                }
+                sensor => Err(format!("{} is not an imager", sensor.id())),
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self::ZernikeProjection {
+            noll,
+            reconstructors,
+        })
+    }
+
+    /// Wavefront error after removing the zernike modes `noll` (of radius
+    /// `radius`, in the pupil) from the measurements of each of the
+    /// `sensors`. Modes that a sensor is blind to (e.g., piston for a SH-WFS)
+    /// have nothing to remove, so they are skipped.
+    fn new_residual_wavefront_error(
+        noll: &[u32],
+        radius: f64,
+        sensors: &[Arc<Sensor>],
+    ) -> Result<Self, String> {
+        let modes = noll
+            .iter()
+            .map(|&j| {
+                let nm = zernike_index::Convention::Noll
+                    .index_to_nm(j)
+                    .ok_or(format!("invalid Noll index {j}"))?;
+                Ok(Disturbance::new_zernike(
+                    format!("Z{j}"),
+                    vec![(nm, 1.0)],
+                    radius,
+                    0.0,
+                    0.0,
+                    Outside::Extrapolate,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let fits = sensors
+            .iter()
+            .map(|sensor| {
+                let measurements = sensor.valid_measurements();
+                let responses: Vec<Array1<f64>> = modes
+                    .iter()
+                    .map(|mode| measurements.iter().map(|m| m.sample(mode)).collect())
+                    .collect();
+                let norms: Vec<f64> = responses.iter().map(|r| r.dot(r).sqrt()).collect();
+                let largest = norms.iter().fold(0.0f64, |acc, norm| acc.max(*norm));
+                let seen: Vec<&Array1<f64>> = responses
+                    .iter()
+                    .zip(&norms)
+                    .filter_map(|(response, norm)| (*norm > 1e-9 * largest).then_some(response))
+                    .collect();
+                let responses =
+                    Array2::from_shape_fn((measurements.len(), seen.len()), |(i, k)| seen[k][i]);
+                let reconstructor = linalg::least_squares(&responses).ok_or(format!(
+                    "too few valid measurements in {} to fit the removed modes",
+                    sensor.id()
+                ))?;
+                Ok((
+                    sensor.id().clone(),
+                    ModeFit {
+                        responses,
+                        reconstructor,
+                    },
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self::ResidualWavefrontError { fits })
+    }
+
+    /// Image quality of the PSF of each of the `sensors`, which must all be
+    /// imagers. Phase is converted to radians at `wavelength` by
+    /// `radians_per_unit`.
+    fn new_image(
+        wavelength: f64,
+        radians_per_unit: f64,
+        oversampling: usize,
+        quantity: ImageQuantity,
+        psf_prefix: Option<String>,
+        sensors: &[Arc<Sensor>],
+    ) -> Result<Self, String> {
+        only_imagers(sensors)?;
+        Ok(Self::Image {
+            imaging: Imaging {
+                wavelength,
+                radians_per_unit,
+                oversampling,
+            },
+            quantity,
+            psf_prefix,
+        })
+    }
+
+    /// Marechal approximation of the Strehl ratio of each of the `sensors`,
+    /// which must all be imagers.
+    fn new_marechal_strehl(radians_per_unit: f64, sensors: &[Arc<Sensor>]) -> Result<Self, String> {
+        only_imagers(sensors)?;
+        Ok(Self::MarechalStrehl { radians_per_unit })
+    }
+
     pub fn evaluate(&self, sensor: &Sensor, disturbances: Vec<Arc<Disturbance>>) -> Vec<f64> {
         match self {
             Metric::WavefrontError => match sensor {
-                Sensor::Shwfs { measurements, .. } => {
+                Sensor::Shwfs {
+                    measurements,
+                    valid,
+                    ..
+                }
+                | Sensor::Pyramid {
+                    measurements,
+                    valid,
+                    ..
+                } => {
                     let mut rms: f64 = 0.0;
-                    for measurement in measurements {
+                    let mut count: usize = 0;
+                    for (measurement, _) in measurements.iter().zip(valid).filter(|(_, v)| **v) {
                         let mut total_disturbance: f64 = 0.0;
                         for disturbance in disturbances.clone() {
                             total_disturbance += measurement.sample(disturbance.as_ref());
                         }
-                        rms += total_disturbance.powf(2.0);
+                        rms += sensor.signal(total_disturbance).powf(2.0);
+                        count += 1;
                     }
-                    rms /= measurements.len() as f64;
-                    vec![rms.sqrt()] // arcsec
+                    rms /= count as f64;
+                    vec![rms.sqrt()] // phase unit per metre, or normalised pyramid signal
                 }
-                Sensor::Imager { measurements, .. } => {
+                Sensor::Imager {
+                    measurements,
+                    valid,
+                    ..
+                } => {
                     let mut rms: f64 = 0.0;
                     let mut mean: f64 = 0.0;
-                    for measurement in measurements {
+                    let mut count: usize = 0;
+                    for (measurement, _) in measurements.iter().zip(valid).filter(|(_, v)| **v) {
                         let mut total_disturbance: f64 = 0.0;
                         for disturbance in disturbances.clone() {
                             total_disturbance += measurement.sample(disturbance.as_ref());
                         }
                         rms += total_disturbance.powf(2.0);
                         mean += total_disturbance;
+                        count += 1;
                     }
-                    rms /= measurements.len() as f64;
-                    mean /= measurements.len() as f64;
+                    rms /= count as f64;
+                    mean /= count as f64;
                     rms -= mean.powf(2.0);
                     rms = rms.sqrt();
-                    vec![rms] // radians
+                    vec![rms] // in the phase unit
+                }
+                Sensor::ZernikeProjection { measurements, .. } => {
+                    let phase = sample_total(measurements, &disturbances);
+                    let mean = phase.mean().unwrap_or(0.0);
+                    let variance = phase.mapv(|v| (v - mean).powi(2)).mean().unwrap_or(0.0);
+                    vec![variance.sqrt()] // in the phase unit
+                }
+                Sensor::Curvature {
+                    measurements,
+                    weights,
+                    nsignals,
+                    ..
+                }
+                | Sensor::LowOrder {
+                    measurements,
+                    weights,
+                    nsignals,
+                    ..
+                } => {
+                    let samples = sample_total(measurements, &disturbances);
+                    let signals = Array1::from(weighted_signals(&samples, weights, *nsignals));
+                    vec![signals.mapv(|v| v * v).mean().unwrap_or(0.0).sqrt()]
                 }
             },
-            Metric::MeasurementVector => match sensor {
-                Sensor::Shwfs { measurements, .. } => {
+            // measurements flagged as masked by the pupil are reported as NaN
+            Metric::MeasurementVector => sensor.add_noise(match sensor {
+                Sensor::Shwfs {
+                    measurements,
+                    valid,
+                    ..
+                }
+                | Sensor::Pyramid {
+                    measurements,
+                    valid,
+                    ..
+                } => {
                     measurements
                         .par_iter()
-                        .map(|meas| {
-                            disturbances
-                                .clone()
-                                .into_par_iter()
-                                .map(|dist| meas.sample(dist.as_ref()))
-                                .sum()
+                        .zip(valid)
+                        .map(|(meas, valid)| match valid {
+                            true => sensor.signal(
+                                disturbances
+                                    .clone()
+                                    .into_par_iter()
+                                    .map(|dist| meas.sample(dist.as_ref()))
+                                    .sum(),
+                            ),
+                            false => f64::NAN,
                         })
-                        .collect() // arcsec
+                        .collect() // phase unit per metre, or normalised pyramid signal
                 }
-                Sensor::Imager { measurements, .. } => {
+                Sensor::Imager {
+                    measurements,
+                    valid,
+                    ..
+                } => {
                     measurements
                         .par_iter()
-                        .map(|meas| {
-                            disturbances
+                        .zip(valid)
+                        .map(|(meas, valid)| match valid {
+                            true => disturbances
                                 .clone()
                                 .into_iter()
                                 .map(|dist| meas.sample(dist.as_ref()))
-                                .sum()
+                                .sum(),
+                            false => f64::NAN,
                         })
-                        .collect() // radians
+                        .collect() // in the phase unit
+                }
+                Sensor::ZernikeProjection {
+                    measurements,
+                    reconstructor,
+                    ..
+                } => reconstructor
+                    .dot(&sample_total(measurements, &disturbances))
+                    .to_vec(), // rms in the phase unit, in the normalisation of zernike disturbances
+                Sensor::Curvature {
+                    measurements,
+                    weights,
+                    nsignals,
+                    ..
+                }
+                | Sensor::LowOrder {
+                    measurements,
+                    weights,
+                    nsignals,
+                    ..
+                } => weighted_signals(
+                    &sample_total(measurements, &disturbances),
+                    weights,
+                    *nsignals,
+                ),
+            }),
+            Metric::ZernikeProjection { reconstructors, .. } => match sensor {
+                Sensor::Imager {
+                    id,
+                    measurements,
+                    valid,
+                    ..
+                } => {
+                    let phase: Array1<f64> = sample_total(measurements, &disturbances)
+                        .into_iter()
+                        .zip(valid)
+                        .filter_map(|(phase, valid)| valid.then_some(phase))
+                        .collect();
+                    reconstructors[id].dot(&phase).to_vec() // in the phase unit
                 }
+                _ => unreachable!("only imagers are projected, see new_zernike_projection"),
             },
+            Metric::ResidualWavefrontError { fits } => {
+                let values: Array1<f64> = sensor
+                    .valid_measurements()
+                    .par_iter()
+                    .map(|meas| {
+                        disturbances
+                            .iter()
+                            .map(|dist| meas.sample(dist.as_ref()))
+                            .sum::<f64>()
+                    })
+                    .collect::<Vec<f64>>()
+                    .into();
+                let residual = fits[sensor.id()].residual(values);
+                let values = match sensor {
+                    Sensor::Shwfs { .. } | Sensor::Pyramid { .. } => {
+                        residual.mapv(|v| sensor.signal(v))
+                    }
+                    Sensor::Imager { .. } | Sensor::ZernikeProjection { .. } => residual,
+                    Sensor::Curvature {
+                        weights, nsignals, ..
+                    }
+                    | Sensor::LowOrder {
+                        weights, nsignals, ..
+                    } => weighted_signals(&residual, weights, *nsignals).into(),
+                };
+                vec![values.mapv(|v| v * v).mean().unwrap_or(0.0).sqrt()]
+            }
+            Metric::Image {
+                imaging, quantity, ..
+            } => {
+                let psf = imaging.psf(sensor, &disturbances);
+                vec![match quantity {
+                    ImageQuantity::Strehl => psf.strehl(),
+                    ImageQuantity::Fwhm => psf.fwhm(),
+                    ImageQuantity::EncircledEnergy(radius) => psf.encircled_energy(*radius),
+                }]
+            }
+            Metric::ErrorBudget { .. } => Metric::WavefrontError.evaluate(sensor, disturbances),
+            Metric::MarechalStrehl { radians_per_unit } => {
+                let rms = Metric::WavefrontError.evaluate(sensor, disturbances)[0];
+                vec![(-(rms * radians_per_unit).powi(2)).exp()]
+            }
         }
     }
 }
 
 impl Output {
-    pub fn evaluate(&self) -> SimulationResult {
+    fn new(
+        id: String,
+        sensors: Vec<Arc<Sensor>>,
+        disturbances: Vec<Vec<Arc<Disturbance>>>,
+        metric: Metric,
+    ) -> Self {
+        let mut output = Self {
+            id,
+            sensors,
+            disturbances,
+            metric,
+            outside_support: BTreeMap::new(),
+        };
+        output.outside_support = output.count_outside_support();
+        output
+    }
+
+    pub fn evaluate(&self) -> Result<SimulationResult, ResultsError> {
         let mut result = SimulationResult::new_from_output(self);
-        let values: Vec<f64> = self.sensors
+        result.outside_support = self.outside_support.clone();
+        for disturbance in self.disturbances.iter().flatten() {
+            let count = result.outside_support[disturbance.id()];
+            if disturbance.errors_outside() && count > 0 {
+                return Err(ResultsError::OutsideSupport {
+                    output: self.id.clone(),
+                    disturbance: disturbance.id().clone(),
+                    count,
+                });
+            }
+        }
+        let values: Vec<f64> = self
+            .sensors
             .par_iter()
-            .flat_map(|sensor| 
-                self.metric.evaluate(sensor, self.disturbances.clone())
-            ).collect();
+            .zip(&self.disturbances)
+            .flat_map(|(sensor, disturbances)| self.metric.evaluate(sensor, disturbances.clone()))
+            .collect();
         result.values = values;
-        result
+        if let Metric::Image {
+            imaging,
+            psf_prefix: Some(prefix),
+            ..
+        } = &self.metric
+        {
+            for (sensor, disturbances) in self.sensors.iter().zip(&self.disturbances) {
+                let psf = imaging.psf(sensor, disturbances);
+                mapfile::write_npy(&format!("{prefix}{}.npy", sensor.id()), &psf.image)?;
+            }
+        }
+        if let Metric::ErrorBudget { cross_terms } = self.metric {
+            result.budget = self.error_budget(cross_terms);
+            result
+                .budget
+                .insert(BUDGET_TOTAL.to_string(), result.values.clone());
+        }
+        Ok(result)
+    }
+
+    /// Wavefront error of each sensor due to each disturbance (by id) alone
+    /// and, if `cross_terms`, the cross term of each pair of disturbances.
+    fn error_budget(&self, cross_terms: bool) -> BTreeMap<String, Vec<f64>> {
+        let ids: BTreeSet<&String> = self
+            .disturbances
+            .iter()
+            .flatten()
+            .map(|disturbance| disturbance.id())
+            .collect();
+        // wavefront error of each sensor due to the disturbances with given ids
+        let wfe = |ids: &[&String]| -> Vec<f64> {
+            self.sensors
+                .par_iter()
+                .zip(&self.disturbances)
+                .flat_map(|(sensor, disturbances)| {
+                    let seen = disturbances
+                        .iter()
+                        .filter(|disturbance| ids.contains(&disturbance.id()))
+                        .cloned()
+                        .collect();
+                    Metric::WavefrontError.evaluate(sensor, seen)
+                })
+                .collect()
+        };
+        let mut budget: BTreeMap<String, Vec<f64>> =
+            ids.iter().map(|id| (id.to_string(), wfe(&[id]))).collect();
+        if cross_terms {
+            for (i, a) in ids.iter().enumerate() {
+                for b in ids.iter().skip(i + 1) {
+                    // signed square root of twice the covariance of a and b,
+                    // so that the signed squares of all of the terms add up
+                    // to the square of the total
+                    let cross = wfe(&[a, b])
+                        .iter()
+                        .zip(&budget[*a])
+                        .zip(&budget[*b])
+                        .map(|((ab, a), b)| ab * ab - a * a - b * b)
+                        .map(|c: f64| c.signum() * c.abs().sqrt())
+                        .collect();
+                    budget.insert(cross_term_label(a, b), cross);
+                }
+            }
+        }
+        budget
+    }
+
+    /// Number of sample lines of valid measurements (over all sensors of
+    /// this output that see it) which fall outside the support of each
+    /// disturbance.
+    fn count_outside_support(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for (sensor, disturbances) in self.sensors.iter().zip(&self.disturbances) {
+            let lines: Vec<Line> = sensor
+                .valid_measurements()
+                .iter()
+                .flat_map(|measurement| measurement.get_bundle())
+                .map(|(line, _)| line)
+                .collect();
+            for disturbance in disturbances {
+                let count = lines
+                    .par_iter()
+                    .filter(|line| disturbance.outside_support(line))
+                    .count();
+                *counts.entry(disturbance.id().clone()).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+}
+
+fn only_imagers(sensors: &[Arc<Sensor>]) -> Result<(), String> {
+    match sensors
+        .iter()
+        .find(|sensor| !matches!(sensor.as_ref(), Sensor::Imager { .. }))
+    {
+        Some(sensor) => Err(format!("{} is not an imager", sensor.id())),
+        None => Ok(()),
+    }
+}
+
+/// Label of the total in an error budget, which no disturbance may have.
+const BUDGET_TOTAL: &str = "total";
+
+/// Label of the cross term of disturbances `a` and `b` in an error budget,
+/// which no other disturbance of the budget may have.
+fn cross_term_label(a: &str, b: &str) -> String {
+    format!("{a} x {b}")
+}
+
+/// Total of the disturbances sampled by each measurement.
+fn sample_total(measurements: &[Measurement], disturbances: &[Arc<Disturbance>]) -> Array1<f64> {
+    measurements
+        .par_iter()
+        .map(|meas| {
+            disturbances
+                .iter()
+                .map(|dist| meas.sample(dist.as_ref()))
+                .sum()
+        })
+        .collect::<Vec<f64>>()
+        .into()
+}
+
+/// Accumulate weighted samples into signals, e.g., of a curvature WFS.
+fn weighted_signals(samples: &Array1<f64>, weights: &[(usize, f64)], nsignals: usize) -> Vec<f64> {
+    let mut signals = vec![0.0; nsignals];
+    for (sample, (index, weight)) in samples.iter().zip(weights) {
+        signals[*index] += weight * sample;
+    }
+    signals
+}
+
+/// Position at which a measurement's principal line crosses the pupil plane.
+fn pupil_position(measurement: &Measurement) -> Vec2D {
+    match measurement {
+        Measurement::Phase { line } => line.position_at_altitude(0.0),
+        Measurement::SlopeTwoEdge { central_line, .. } => central_line.position_at_altitude(0.0),
+        measurement => {
+            let bundle = measurement.get_bundle();
+            let n = bundle.len().max(1) as f64;
+            bundle.iter().fold(Vec2D::new(0.0, 0.0), |acc, (line, _)| {
+                acc + line.position_at_altitude(0.0) / n
+            })
+        }
     }
 }
 
@@ -329,6 +1848,31 @@ impl Output {
 pub struct SimulationResult {
     pub id: String,
     pub values: Vec<f64>,
+    /// number of sample lines of valid measurements outside the support of
+    /// each disturbance
+    #[serde(default)]
+    pub outside_support: BTreeMap<String, usize>,
+    /// valid-subaperture map of each SH-WFS of this output, one entry per
+    /// lenslet in layout order (row-major, x fastest, for a square or
+    /// hexagonal array, or in the order given for an explicit layout).
+    /// Slope vectors hold the x slopes of the valid subapertures in this
+    /// order, followed by their y slopes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub valid_subapertures: BTreeMap<String, Vec<bool>>,
+    /// Noll index of each coefficient reported by each zernike projection
+    /// sensor of this output, or by each imager of a zernike projection
+    /// output
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub noll_indices: BTreeMap<String, Vec<u32>>,
+    /// wavefront error budget of an error budget output, one value per
+    /// sensor for each disturbance (by id) alone, for all of them
+    /// (`"total"`) and optionally the cross term of each pair (`"a x b"`).
+    /// Cross terms are the signed square root of the difference of squares
+    /// between the pair together and alone, so for sensors that are linear
+    /// in the phase (i.e., not pyramids in the `Geometric` regime) the sum
+    /// of the signed squares of all terms is the square of the total.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub budget: BTreeMap<String, Vec<f64>>,
 }
 
 impl SimulationResult {
@@ -336,6 +1880,44 @@ impl SimulationResult {
         Self {
             id: output.id.clone(),
             values: vec![],
+            outside_support: BTreeMap::new(),
+            valid_subapertures: output
+                .sensors
+                .iter()
+                .filter_map(|sensor| match sensor.as_ref() {
+                    Sensor::Shwfs {
+                        id, subapertures, ..
+                    }
+                    | Sensor::Curvature {
+                        id, subapertures, ..
+                    }
+                    | Sensor::LowOrder {
+                        id, subapertures, ..
+                    } => Some((id.clone(), subapertures.clone())),
+                    Sensor::Imager { .. }
+                    | Sensor::Pyramid { .. }
+                    | Sensor::ZernikeProjection { .. } => None,
+                })
+                .collect(),
+            noll_indices: output
+                .sensors
+                .iter()
+                .filter_map(|sensor| match sensor.as_ref() {
+                    Sensor::ZernikeProjection { id, noll, .. } => Some((id.clone(), noll.clone())),
+                    Sensor::Shwfs { .. }
+                    | Sensor::Imager { .. }
+                    | Sensor::Pyramid { .. }
+                    | Sensor::Curvature { .. }
+                    | Sensor::LowOrder { .. } => None,
+                })
+                .chain(output.sensors.iter().filter_map(|sensor| {
+                    let Metric::ZernikeProjection { noll, .. } = &output.metric else {
+                        return None;
+                    };
+                    Some((sensor.id().clone(), noll.clone()))
+                }))
+                .collect(),
+            budget: BTreeMap::new(),
         }
     }
 }
@@ -362,13 +1944,13 @@ impl SimulationResults {
 }
 
 impl System {
-    pub fn evaluate(&self) -> SimulationResults {
-        SimulationResults {
+    pub fn evaluate(&self) -> Result<SimulationResults, ResultsError> {
+        Ok(SimulationResults {
             results: self
                 .outputs
                 .iter()
                 .map(|output| output.evaluate())
-                .collect(),
-        }
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
@@ -0,0 +1,48 @@
+//! Small dense linear algebra helpers, for fitting modes to sampled phase.
+
+use ndarray::Array2;
+
+/// Invert a square matrix by Gauss-Jordan elimination with partial pivoting,
+/// `None` if the matrix is (numerically) singular.
+pub(crate) fn invert(matrix: &Array2<f64>) -> Option<Array2<f64>> {
+    let n = matrix.nrows();
+    assert_eq!(n, matrix.ncols(), "only square matrices can be inverted");
+    let mut a = matrix.clone();
+    let mut inverse = Array2::<f64>::eye(n);
+    let tolerance = 1e-12 * a.iter().fold(0.0f64, |acc, v| acc.max(v.abs()));
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[[i, col]].abs().total_cmp(&a[[j, col]].abs()))
+            .unwrap_or(col);
+        if a[[pivot, col]].abs() <= tolerance {
+            return None;
+        }
+        for k in 0..n {
+            a.swap([col, k], [pivot, k]);
+            inverse.swap([col, k], [pivot, k]);
+        }
+        let scale = a[[col, col]];
+        a.row_mut(col).mapv_inplace(|v| v / scale);
+        inverse.row_mut(col).mapv_inplace(|v| v / scale);
+        for row in 0..n {
+            if row == col {
+                continue;
+            }
+            let factor = a[[row, col]];
+            if factor != 0.0 {
+                for k in 0..n {
+                    a[[row, k]] -= factor * a[[col, k]];
+                    inverse[[row, k]] -= factor * inverse[[col, k]];
+                }
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Least-squares reconstructor `(A^T A)^-1 A^T` of a matrix `A` with more
+/// rows than columns, `None` if `A` doesn't have full column rank.
+pub(crate) fn least_squares(a: &Array2<f64>) -> Option<Array2<f64>> {
+    let normal = a.t().dot(a);
+    Some(invert(&normal)?.dot(&a.t()))
+}
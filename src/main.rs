@@ -41,8 +41,8 @@ fn main() -> Result<()> {
             Config::from_file(&filename)?
         }
     };
-    let system = system_config.to_system();
-    let results = system.evaluate();
+    let system = system_config.to_system()?;
+    let results = system.evaluate()?;
     match output {
         Some(filename) => {
             // save to filename
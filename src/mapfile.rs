@@ -0,0 +1,208 @@
+//! Readers for 2D phase maps stored on disk, and a writer for `.npy` files.
+//!
+//! Two formats are supported:
+//!  - numpy `.npy` files (little-endian `f4` or `f8`, C or Fortran order),
+//!  - raw little-endian binary files, described by a JSON sidecar file with
+//!    the same name and a `.json` extension, e.g., `adc.bin` and `adc.json`:
+//!    ```json
+//!    {"shape": [ny, nx], "dtype": "f64"}
+//!    ```
+//!    where `dtype` is one of `"f32"` or `"f64"`.
+
+use ndarray::Array2;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use crate::config::ConfigError;
+
+#[derive(Deserialize)]
+struct Sidecar {
+    shape: (usize, usize),
+    dtype: String,
+}
+
+/// Read a 2D map, indexed `[[iy, ix]]`.
+pub(crate) fn read_map(filename: &str) -> Result<Array2<f64>, ConfigError> {
+    let bytes = fs::read(filename)?;
+    if bytes.starts_with(b"\x93NUMPY") {
+        read_npy(filename, &bytes)
+    } else {
+        let sidecar_name = Path::new(filename).with_extension("json");
+        let sidecar: Sidecar = serde_json::from_str(&fs::read_to_string(&sidecar_name)?)?;
+        from_le_bytes(filename, &bytes, &sidecar.dtype, sidecar.shape, false)
+    }
+}
+
+/// Write a 2D map, indexed `[[iy, ix]]`, as a little-endian `f8` numpy
+/// `.npy` file in C order.
+pub(crate) fn write_npy(filename: &str, map: &Array2<f64>) -> std::io::Result<()> {
+    let (ny, nx) = map.dim();
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({ny}, {nx}), }}");
+    // the magic string, version and header length take 10 bytes, and the
+    // header is padded with spaces (and a newline) to a multiple of 64 bytes
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend((header.len() as u16).to_le_bytes());
+    bytes.extend(header.as_bytes());
+    bytes.extend(map.iter().flat_map(|v| v.to_le_bytes()));
+    fs::write(filename, bytes)
+}
+
+fn invalid(filename: &str, msg: &str) -> ConfigError {
+    ConfigError::MapFile(filename.to_string(), msg.to_string())
+}
+
+fn read_npy(filename: &str, bytes: &[u8]) -> Result<Array2<f64>, ConfigError> {
+    let truncated = || invalid(filename, "truncated npy header");
+    let major = *bytes.get(6).ok_or_else(truncated)?;
+    let (header_len, header_start) = match major {
+        1 => (
+            u16::from_le_bytes(bytes.get(8..10).ok_or_else(truncated)?.try_into().unwrap())
+                as usize,
+            10,
+        ),
+        2 | 3 => (
+            u32::from_le_bytes(bytes.get(8..12).ok_or_else(truncated)?.try_into().unwrap())
+                as usize,
+            12,
+        ),
+        _ => return Err(invalid(filename, "unsupported npy version")),
+    };
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .ok_or_else(truncated)?;
+    let header = String::from_utf8_lossy(header);
+    // the header is a python dict literal, e.g.:
+    // {'descr': '<f8', 'fortran_order': False, 'shape': (64, 64), }
+    let field = |key: &str| -> Option<&str> {
+        let start = header.find(&format!("'{key}':"))? + key.len() + 3;
+        Some(header[start..].trim_start())
+    };
+    let descr = field("descr")
+        .and_then(|s| s.split('\'').nth(1))
+        .ok_or_else(|| invalid(filename, "missing descr in npy header"))?;
+    let dtype = match descr {
+        "<f8" => "f64",
+        "<f4" => "f32",
+        other => return Err(invalid(filename, &format!("unsupported npy dtype {other}"))),
+    };
+    let fortran_order = field("fortran_order")
+        .map(|s| s.starts_with("True"))
+        .unwrap_or(false);
+    let shape: Vec<usize> = field("shape")
+        .and_then(|s| s.strip_prefix('('))
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| invalid(filename, "missing shape in npy header"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid(filename, "could not parse npy shape"))?;
+    let [ny, nx] = shape[..] else {
+        return Err(invalid(filename, "npy array must be 2D"));
+    };
+    from_le_bytes(
+        filename,
+        &bytes[header_start + header_len..],
+        dtype,
+        (ny, nx),
+        fortran_order,
+    )
+}
+
+fn from_le_bytes(
+    filename: &str,
+    bytes: &[u8],
+    dtype: &str,
+    (ny, nx): (usize, usize),
+    fortran_order: bool,
+) -> Result<Array2<f64>, ConfigError> {
+    let values: Vec<f64> = match dtype {
+        "f64" => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        "f32" => bytes
+            .chunks_exact(4)
+            .map(|b| f64::from(f32::from_le_bytes(b.try_into().unwrap())))
+            .collect(),
+        other => return Err(invalid(filename, &format!("unsupported dtype {other}"))),
+    };
+    if values.len() != ny * nx {
+        return Err(invalid(
+            filename,
+            &format!("expected {} values, found {}", ny * nx, values.len()),
+        ));
+    }
+    let map = if fortran_order {
+        Array2::from_shape_vec((nx, ny), values).map(|a| a.reversed_axes())
+    } else {
+        Array2::from_shape_vec((ny, nx), values)
+    };
+    map.map_err(|e| invalid(filename, &e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rao-forward-{}-{name}", std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn example() -> Array2<f64> {
+        Array2::from_shape_fn((3, 4), |(iy, ix)| (10 * iy + ix) as f64)
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let filename = temp_file("round-trip.npy");
+        write_npy(&filename, &example()).unwrap();
+        let map = read_map(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(map, example());
+    }
+
+    #[test]
+    fn npy_fortran_order_f4() {
+        // version 2.0 header, written as numpy does for a transposed array
+        let mut header = "{'descr': '<f4', 'fortran_order': True, 'shape': (3, 4), }".to_string();
+        header.push_str(&" ".repeat(63 - (12 + header.len()) % 64));
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x02\x00".to_vec();
+        bytes.extend((header.len() as u32).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        let map = example();
+        bytes.extend(map.t().iter().flat_map(|v| (*v as f32).to_le_bytes()));
+        let filename = temp_file("fortran.npy");
+        fs::write(&filename, bytes).unwrap();
+        let read = read_map(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(read, map);
+    }
+
+    #[test]
+    fn raw_with_sidecar() {
+        let filename = temp_file("raw.bin");
+        let sidecar = Path::new(&filename).with_extension("json");
+        let map = example();
+        fs::write(
+            &filename,
+            map.iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        )
+        .unwrap();
+        fs::write(&sidecar, r#"{"shape": [3, 4], "dtype": "f64"}"#).unwrap();
+        let read = read_map(&filename);
+        fs::remove_file(&filename).unwrap();
+        fs::remove_file(&sidecar).unwrap();
+        assert_eq!(read.unwrap(), map);
+    }
+}
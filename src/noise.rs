@@ -0,0 +1,74 @@
+//! Detector noise on SH-WFS style slope measurements.
+//!
+//! The centroid of a Gaussian spot of `N` photons has a photon noise variance
+//! of `sigma_spot^2 / N`. Read noise and sky background add
+//! `(ron^2 + background) * sum(x_i^2) / N^2`, summed over the pixels `x_i` of
+//! the centroiding window (Rousset, 1999). All angles are in arcsec, until
+//! the slope noise is converted into the units of the slopes.
+
+use crate::random::Rng;
+
+pub(crate) struct NoiseModel {
+    /// photons per frame reaching a fully illuminated subaperture
+    flux: f64,
+    /// read noise (electrons rms per pixel)
+    read_noise: f64,
+    /// sky background (photons per pixel per frame)
+    background: f64,
+    /// pixel scale (arcsec per pixel)
+    pixel_scale: f64,
+    /// spot FWHM (arcsec)
+    spot_size: f64,
+    /// slope (in phase unit per metre) per arcsec of tilt
+    slope_per_arcsec: f64,
+    seed: u64,
+}
+
+impl NoiseModel {
+    pub(crate) fn new(
+        flux: f64,
+        read_noise: f64,
+        background: f64,
+        pixel_scale: f64,
+        spot_size: f64,
+        slope_per_arcsec: f64,
+        seed: u64,
+    ) -> Self {
+        Self {
+            flux,
+            read_noise,
+            background,
+            pixel_scale,
+            spot_size,
+            slope_per_arcsec,
+            seed,
+        }
+    }
+
+    /// Standard deviation (in slope units) of each slope of a subaperture
+    /// receiving `fraction` of the full flux.
+    pub(crate) fn slope_sigma(&self, fraction: f64) -> f64 {
+        let photons = self.flux * fraction;
+        if photons <= 0.0 {
+            return f64::INFINITY;
+        }
+        let spot_sigma = self.spot_size / (8.0 * 2f64.ln()).sqrt();
+        let photon_variance = spot_sigma.powi(2) / photons;
+        // square centroiding window, two spot FWHMs across
+        let npix = ((2.0 * self.spot_size / self.pixel_scale).ceil() as usize).max(2) as f64;
+        // sum of squared pixel offsets from the window centre, along one axis
+        let sum_x2 = self.pixel_scale.powi(2) * npix.powi(2) * (npix.powi(2) - 1.0) / 12.0;
+        let detector_variance =
+            (self.read_noise.powi(2) + self.background) * sum_x2 / photons.powi(2);
+        (photon_variance + detector_variance).sqrt() * self.slope_per_arcsec
+    }
+
+    /// Add a seeded Gaussian realisation of the noise, with standard
+    /// deviation `sigma[i]`, to each of the `values`.
+    pub(crate) fn add_to(&self, values: &mut [f64], sigma: &[f64]) {
+        let mut rng = Rng::new(self.seed);
+        for (value, sigma) in values.iter_mut().zip(sigma) {
+            *value += sigma * rng.normal();
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Phase screens: regularly gridded phase maps which can be sampled at any
+//! point in the transverse plane.
+
+use core::f64;
+use ndarray::Array2;
+use num_complex::Complex64;
+use rao::Vec2D;
+
+use crate::{fft, random::Rng};
+
+/// Square grid of phase values, centred on the optical axis.
+///
+/// Element `[[iy, ix]]` is located at `((ix - n/2) * pitch, (iy - n/2) * pitch)`
+/// in metres, and the screen is treated as periodic when sampled outside of
+/// its extent (which is natural for FFT-generated screens).
+pub(crate) struct PhaseScreen {
+    values: Array2<f64>,
+    /// distance between adjacent grid points in metres
+    pitch: f64,
+}
+
+/// von Karman phase power spectral density (rad^2 m^2) at spatial frequency `f`
+fn von_karman_psd(f: f64, r0: f64, l0: f64) -> f64 {
+    0.023 * r0.powf(-5.0 / 3.0) * (f.powi(2) + l0.powi(-2)).powf(-11.0 / 6.0)
+}
+
+impl PhaseScreen {
+    /// Generate a von Karman phase screen by the FFT method, including three
+    /// levels of subharmonics (Johansson & Gavel, 1994) to restore the low
+    /// order power lost to the finite grid.
+    ///
+    /// The screen is at least `size` metres across, sampled every `pitch`
+    /// metres. Phase is in radians at the wavelength that `r0` is defined at.
+    pub(crate) fn new_von_karman(r0: f64, l0: f64, size: f64, pitch: f64, seed: u64) -> Self {
+        let n = ((size / pitch).ceil() as usize).max(2).next_power_of_two();
+        let mut rng = Rng::new(seed);
+        let df = 1.0 / (n as f64 * pitch);
+        let freq = |k: usize| -> f64 {
+            if k < n / 2 {
+                k as f64 * df
+            } else {
+                (k as f64 - n as f64) * df
+            }
+        };
+        let mut spectrum = Array2::<Complex64>::zeros((n, n));
+        for ((iy, ix), c) in spectrum.indexed_iter_mut() {
+            if iy == 0 && ix == 0 {
+                continue;
+            }
+            let f = freq(ix).hypot(freq(iy));
+            let amplitude = von_karman_psd(f, r0, l0).sqrt() * df;
+            *c = Complex64::new(rng.normal(), rng.normal()) * amplitude;
+        }
+        fft::fft2(&mut spectrum, true);
+        let mut values = spectrum.mapv(|c| c.re);
+
+        // subharmonics
+        let mut subharmonics = Array2::<f64>::zeros((n, n));
+        for p in 1..=3 {
+            let df_p = df / 3f64.powi(p);
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    if i == 0 && j == 0 {
+                        continue;
+                    }
+                    let (fx, fy) = (i as f64 * df_p, j as f64 * df_p);
+                    let amplitude = von_karman_psd(fx.hypot(fy), r0, l0).sqrt() * df_p;
+                    let c = Complex64::new(rng.normal(), rng.normal()) * amplitude;
+                    for ((iy, ix), v) in subharmonics.indexed_iter_mut() {
+                        let x = (ix as f64 - (n / 2) as f64) * pitch;
+                        let y = (iy as f64 - (n / 2) as f64) * pitch;
+                        let phase = 2.0 * f64::consts::PI * (fx * x + fy * y);
+                        *v += (c * Complex64::from_polar(1.0, phase)).re;
+                    }
+                }
+            }
+        }
+        let mean = subharmonics.mean().unwrap_or(0.0);
+        values += &(subharmonics - mean);
+        Self { values, pitch }
+    }
+
+    /// Multiply the phase of the screen by `factor`, e.g., to change its units.
+    pub(crate) fn scaled(mut self, factor: f64) -> Self {
+        self.values *= factor;
+        self
+    }
+
+    /// Whether a position (in metres) is within the screen, beyond which the
+    /// screen repeats itself.
+    pub(crate) fn contains(&self, pos: &Vec2D) -> bool {
+        let half_width = self.values.nrows() as f64 * self.pitch / 2.0;
+        pos.x.abs() <= half_width && pos.y.abs() <= half_width
+    }
+
+    /// Bilinearly interpolate the screen at a position (in metres).
+    pub(crate) fn interpolate(&self, pos: &Vec2D) -> f64 {
+        let n = self.values.nrows() as isize;
+        let fx = pos.x / self.pitch + (n / 2) as f64;
+        let fy = pos.y / self.pitch + (n / 2) as f64;
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+        let at = |ix: isize, iy: isize| -> f64 {
+            self.values[[iy.rem_euclid(n) as usize, ix.rem_euclid(n) as usize]]
+        };
+        let (ix, iy) = (x0 as isize, y0 as isize);
+        (1.0 - ty) * ((1.0 - tx) * at(ix, iy) + tx * at(ix + 1, iy))
+            + ty * ((1.0 - tx) * at(ix, iy + 1) + tx * at(ix + 1, iy + 1))
+    }
+}
+
+/// Interpolation scheme used when sampling a [`PhaseMap`].
+pub(crate) enum Interpolation {
+    Bilinear,
+    Bicubic,
+}
+
+/// Rectangular grid of phase values covering a fixed physical extent,
+/// centred on the optical axis. The map is zero outside of its extent, and
+/// non-finite values (e.g., NaN outside a measured aperture) are treated as
+/// zero.
+///
+/// Element `[[iy, ix]]` is the value at the centre of the corresponding
+/// pixel, so the map of width `w` spans `-w/2..w/2` along x.
+pub(crate) struct PhaseMap {
+    values: Array2<f64>,
+    /// physical width and height of the map (in metres)
+    extent: (f64, f64),
+    interpolation: Interpolation,
+}
+
+impl PhaseMap {
+    pub(crate) fn new(
+        values: Array2<f64>,
+        extent: (f64, f64),
+        interpolation: Interpolation,
+    ) -> Self {
+        let values = values.mapv(|v| if v.is_finite() { v } else { 0.0 });
+        Self {
+            values,
+            extent,
+            interpolation,
+        }
+    }
+
+    /// Whether a position (in metres) is within the extent of the map.
+    pub(crate) fn contains(&self, pos: &Vec2D) -> bool {
+        pos.x.abs() <= self.extent.0 / 2.0 && pos.y.abs() <= self.extent.1 / 2.0
+    }
+
+    /// Interpolate the map at a position (in metres).
+    pub(crate) fn interpolate(&self, pos: &Vec2D) -> f64 {
+        if !self.contains(pos) {
+            return 0.0;
+        }
+        let (ny, nx) = self.values.dim();
+        // fractional pixel indices
+        let fx = (pos.x / self.extent.0 + 0.5) * nx as f64 - 0.5;
+        let fy = (pos.y / self.extent.1 + 0.5) * ny as f64 - 0.5;
+        let at = |ix: isize, iy: isize| -> f64 {
+            // replicate edge pixels so the map doesn't roll off inside its extent
+            let ix = ix.clamp(0, nx as isize - 1) as usize;
+            let iy = iy.clamp(0, ny as isize - 1) as usize;
+            self.values[[iy, ix]]
+        };
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+        let (ix, iy) = (x0 as isize, y0 as isize);
+        match self.interpolation {
+            Interpolation::Bilinear => {
+                (1.0 - ty) * ((1.0 - tx) * at(ix, iy) + tx * at(ix + 1, iy))
+                    + ty * ((1.0 - tx) * at(ix, iy + 1) + tx * at(ix + 1, iy + 1))
+            }
+            Interpolation::Bicubic => (-1..=2)
+                .flat_map(|j| (-1..=2).map(move |i| (i, j)))
+                .map(|(i, j)| {
+                    at(ix + i, iy + j) * keys_cubic(tx - i as f64) * keys_cubic(ty - j as f64)
+                })
+                .sum(),
+        }
+    }
+}
+
+/// Keys cubic convolution kernel (a = -0.5), which is 1.0 at zero and 0.0 at
+/// all other integers.
+pub(crate) fn keys_cubic(x: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        1.5 * x.powi(3) - 2.5 * x.powi(2) + 1.0
+    } else if x < 2.0 {
+        -0.5 * x.powi(3) + 2.5 * x.powi(2) - 4.0 * x + 2.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible_from_seed() {
+        let a = PhaseScreen::new_von_karman(0.1, 25.0, 3.2, 0.05, 7);
+        let b = PhaseScreen::new_von_karman(0.1, 25.0, 3.2, 0.05, 7);
+        let c = PhaseScreen::new_von_karman(0.1, 25.0, 3.2, 0.05, 8);
+        assert_eq!(a.values, b.values);
+        assert_ne!(a.values, c.values);
+    }
+
+    /// `D(r) = <(phi(x + r) - phi(x))^2>`, which for von Karman turbulence is
+    /// `6.88 (r/r0)^(5/3) (1 - 1.485 (r/L0)^(1/3))` to first order in `r/L0`
+    #[test]
+    fn structure_function() {
+        let (r0, l0, pitch) = (0.1, 25.0, 0.05);
+        let screens: Vec<PhaseScreen> = (0..8)
+            .map(|seed| PhaseScreen::new_von_karman(r0, l0, 12.8, pitch, seed))
+            .collect();
+        for shift in [2, 4, 8] {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for screen in &screens {
+                // pairs within the screen only, since the subharmonics aren't
+                // periodic over it
+                let n = screen.values.nrows();
+                for iy in 0..n - shift {
+                    for ix in 0..n - shift {
+                        let v = screen.values[[iy, ix]];
+                        sum += (screen.values[[iy, ix + shift]] - v).powi(2);
+                        sum += (screen.values[[iy + shift, ix]] - v).powi(2);
+                        count += 2;
+                    }
+                }
+            }
+            let measured = sum / count as f64;
+            let r = shift as f64 * pitch;
+            let expected = 6.88 * (r / r0).powf(5.0 / 3.0) * (1.0 - 1.485 * (r / l0).cbrt());
+            let ratio = measured / expected;
+            assert!((ratio - 1.0).abs() < 0.1, "shift={shift}: {ratio}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Zernike mode indexing conventions.
+//!
+//! Internally, every mode is addressed by its radial order `n` and signed
+//! azimuthal order `m`, where `m > 0` is a cosine mode and `m < 0` is a sine
+//! mode. Single-index conventions are converted to and from `(n, m)` here.
+//! The indexing convention doesn't change how modes are evaluated, which is
+//! always done by the `zernike` crate.
+
+use serde::{Deserialize, Serialize};
+
+/// a coefficient paired with the `(n, m)` of its mode
+pub(crate) type NmCoefficient = ((u32, i32), f64);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum Convention {
+    /// Noll (1976), starting at `j = 1` (piston)
+    #[default]
+    Noll,
+    /// OSA/ANSI standard, starting at `j = 0` (piston)
+    Osa,
+    /// Fringe (University of Arizona), starting at `j = 1` (piston). Only
+    /// the standard 37-term set is defined, ending with `j = 37` at
+    /// `(12, 0)`.
+    Fringe,
+}
+
+impl Convention {
+    /// index of piston, i.e., of the first element of a dense coefficient vector
+    pub fn first_index(&self) -> u32 {
+        match self {
+            Convention::Noll | Convention::Fringe => 1,
+            Convention::Osa => 0,
+        }
+    }
+
+    /// Convert a single index to `(n, m)`, `None` if the index is invalid.
+    pub fn index_to_nm(&self, j: u32) -> Option<(u32, i32)> {
+        match self {
+            Convention::Noll => noll_to_nm(j),
+            Convention::Osa => {
+                let n = ((-3.0 + (9.0 + 8.0 * j as f64).sqrt()) / 2.0).ceil() as u32;
+                Some((n, 2 * j as i32 - (n * (n + 2)) as i32))
+            }
+            Convention::Fringe => {
+                if j == 0 || j > FRINGE_TERMS {
+                    return None;
+                }
+                // fringe indices grow quadratically with n, so a short search is enough
+                (0..=12)
+                    .flat_map(|n| (-(n as i32)..=n as i32).step_by(2).map(move |m| (n, m)))
+                    .find(|&(n, m)| self.nm_to_index(n, m) == Some(j))
+            }
+        }
+    }
+
+    /// Convert `(n, m)` to a single index, `None` if `(n, m)` is not a valid mode.
+    pub fn nm_to_index(&self, n: u32, m: i32) -> Option<u32> {
+        let m_abs = m.unsigned_abs();
+        if m_abs > n || !(n - m_abs).is_multiple_of(2) {
+            return None;
+        }
+        match self {
+            Convention::Noll => {
+                let first = n * (n + 1) / 2 + 1;
+                (first..first + n + 1).find(|&j| noll_to_nm(j) == Some((n, m)))
+            }
+            Convention::Osa => Some(((n * (n + 2)) as i32 + m) as u32 / 2),
+            Convention::Fringe => {
+                if (n, m) == (12, 0) {
+                    return Some(FRINGE_TERMS);
+                }
+                // closed form for all but the last of the 37 terms
+                let sine = if m < 0 { 1 } else { 0 };
+                Some((1 + (n + m_abs) / 2).pow(2) - 2 * m_abs + sine).filter(|&j| j < FRINGE_TERMS)
+            }
+        }
+    }
+}
+
+/// number of modes in the standard Fringe set
+const FRINGE_TERMS: u32 = 37;
+
+fn noll_to_nm(j: u32) -> Option<(u32, i32)> {
+    if j == 0 {
+        return None;
+    }
+    let mut n = 0;
+    while (n + 1) * (n + 2) / 2 < j {
+        n += 1;
+    }
+    // position within the radial order
+    let p = j - n * (n + 1) / 2 - 1;
+    let m_abs = if n % 2 == 0 {
+        2 * p.div_ceil(2)
+    } else {
+        2 * (p / 2) + 1
+    };
+    // even j are cosine modes, odd j are sine modes
+    let m = match (m_abs, j % 2) {
+        (0, _) => 0,
+        (_, 0) => m_abs as i32,
+        _ => -(m_abs as i32),
+    };
+    Some((n, m))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (n, m) for the first 50 Noll modes and the 37 Fringe modes, from the
+    /// standard tables.
+    #[rustfmt::skip]
+    const NOLL: [(u32, i32); 50] = [
+        (0, 0), (1, 1), (1, -1), (2, 0), (2, -2), (2, 2), (3, -1), (3, 1), (3, -3), (3, 3),
+        (4, 0), (4, 2), (4, -2), (4, 4), (4, -4), (5, 1), (5, -1), (5, 3), (5, -3), (5, 5),
+        (5, -5), (6, 0), (6, -2), (6, 2), (6, -4), (6, 4), (6, -6), (6, 6), (7, -1), (7, 1),
+        (7, -3), (7, 3), (7, -5), (7, 5), (7, -7), (7, 7), (8, 0), (8, 2), (8, -2), (8, 4),
+        (8, -4), (8, 6), (8, -6), (8, 8), (8, -8), (9, 1), (9, -1), (9, 3), (9, -3), (9, 5),
+    ];
+    /// the standard 37-term Fringe set, which is all of it
+    #[rustfmt::skip]
+    const FRINGE: [(u32, i32); 37] = [
+        (0, 0), (1, 1), (1, -1), (2, 0), (2, 2), (2, -2), (3, 1), (3, -1), (4, 0), (3, 3),
+        (3, -3), (4, 2), (4, -2), (5, 1), (5, -1), (6, 0), (4, 4), (4, -4), (5, 3), (5, -3),
+        (6, 2), (6, -2), (7, 1), (7, -1), (8, 0), (5, 5), (5, -5), (6, 4), (6, -4), (7, 3),
+        (7, -3), (8, 2), (8, -2), (9, 1), (9, -1), (10, 0), (12, 0),
+    ];
+
+    #[test]
+    fn first_50_modes() {
+        let mut osa = vec![];
+        for n in 0..10u32 {
+            for m in (-(n as i32)..=n as i32).step_by(2) {
+                osa.push((n, m));
+            }
+        }
+        for (convention, table) in [
+            (Convention::Noll, &NOLL[..]),
+            (Convention::Osa, &osa[..50]),
+            (Convention::Fringe, &FRINGE[..]),
+        ] {
+            for (i, &(n, m)) in table.iter().enumerate() {
+                let j = convention.first_index() + i as u32;
+                assert_eq!(
+                    convention.index_to_nm(j),
+                    Some((n, m)),
+                    "{convention:?} j={j}"
+                );
+                assert_eq!(
+                    convention.nm_to_index(n, m),
+                    Some(j),
+                    "{convention:?} j={j}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fringe_set_ends_at_37() {
+        assert_eq!(Convention::Fringe.index_to_nm(38), None);
+        assert_eq!(Convention::Fringe.nm_to_index(6, 6), None);
+        assert_eq!(Convention::Fringe.nm_to_index(12, 2), None);
+    }
+}